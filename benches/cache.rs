@@ -0,0 +1,47 @@
+// Stringly-Typed JSON Library for Rust
+// Written in 2015 by
+//   Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Compares loading a document from the binary cache format against
+//! parsing it from text
+
+#[macro_use]
+extern crate criterion;
+extern crate strason;
+
+use criterion::{black_box, Criterion};
+use strason::Json;
+
+/// An array of a few thousand small records, with nested objects and
+/// strings needing escapes
+fn document() -> String {
+    let records: Vec<String> = (0..2000).map(|i| format!(
+        "{{\"id\": {}, \"name\": \"record \\\"{}\\\"\", \"score\": {}.5e-3, \
+         \"tags\": [\"a\", \"b\\u00e9\", null, true], \"nested\": {{\"x\": [[{}]]}}}}",
+        i, i, i * 7, i % 13,
+    )).collect();
+    format!("[{}]", records.join(", "))
+}
+
+fn bench_load(c: &mut Criterion) {
+    let text = document();
+    let bytes = Json::from_str(&text).unwrap().to_cache_bytes();
+
+    c.bench_function("from_str", |b| b.iter(|| Json::from_str(black_box(&text)).unwrap()));
+    c.bench_function("from_cache_bytes", |b| {
+        b.iter(|| Json::from_cache_bytes(black_box(&bytes)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_load);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+// Stringly-Typed JSON Library for Rust
+// Written in 2015 by
+//   Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+
+//! Parsing very long number literals, which should take time linear in
+//! their length
+
+#[macro_use]
+extern crate criterion;
+extern crate strason;
+
+use criterion::{black_box, BenchmarkId, Criterion};
+use strason::Json;
+use strason::parser::Parser;
+
+fn bench_long_number(c: &mut Criterion) {
+    let mut group = c.benchmark_group("long_number");
+    for &len in &[10_000, 100_000, 1_000_000] {
+        let int = "7".repeat(len);
+        let frac = format!("-1.{}e-99", "3".repeat(len));
+        group.bench_with_input(BenchmarkId::new("integer", len), &int, |b, s| {
+            b.iter(|| Json::from_str(black_box(s)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("fraction", len), &frac, |b, s| {
+            b.iter(|| Json::from_str(black_box(s)).unwrap())
+        });
+        // Rejection by the length limit stops reading at the limit
+        group.bench_with_input(BenchmarkId::new("over_limit", len), &int, |b, s| {
+            b.iter(|| {
+                let mut parser = Parser::new(black_box(s).bytes().map(Ok));
+                parser.set_max_number_len(1000);
+                parser.parse().unwrap_err()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_long_number);
+criterion_main!(benches);
@@ -0,0 +1,279 @@
+// Stringly-Typed JSON Library for Rust
+// Written in 2015 by
+//   Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Binary cache format
+//!
+//! A compact encoding of a parsed tree which is quicker to load than Json
+//! text, since nothing needs to be scanned or unescaped. The input starts
+//! with the four bytes `MAGIC`, followed by the root node. Each node is a
+//! one-byte tag, followed for numbers and strings by a length and the
+//! UTF-8 text, for arrays by an element count and the elements, and for
+//! objects by a member count and each key, as a length and text, then its
+//! value. Lengths and counts are little-endian `u64`s.
+//!
+//! The format is only meant to be read back by the same version of this
+//! library; there are no compatibility guarantees.
+//!
+
+use std::{slice, str};
+
+use {Error, ErrorInner, Json, JsonInner};
+
+/// Identifies the format and its version
+const MAGIC: &[u8; 4] = b"SJC\x01";
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+const TAG_ARRAY: u8 = 5;
+const TAG_OBJECT: u8 = 6;
+
+/// Encode a tree in the cache format
+pub fn encode(json: &Json) -> Vec<u8> {
+    let mut ret = MAGIC.to_vec();
+    // The children of each container entered but not yet finished, kept
+    // on the heap so that nesting depth is not limited by the call stack
+    let mut stack = vec![];
+    let mut next = Some(json);
+    while let Some(json) = next {
+        encode_node(json, &mut ret, &mut stack);
+        next = None;
+        while let Some(top) = stack.last_mut() {
+            next = match *top {
+                Children::Elements(ref mut iter) => iter.next(),
+                Children::Members(ref mut iter) => iter.next().map(|(key, val)| {
+                    encode_str(key, &mut ret);
+                    val
+                }),
+            };
+            if next.is_some() {
+                break;
+            }
+            stack.pop();
+        }
+    }
+    ret
+}
+
+/// The children of a container still to be encoded
+enum Children<'a> {
+    Elements(slice::Iter<'a, Json>),
+    Members(slice::Iter<'a, (String, Json)>),
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    encode_len(s.len(), out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Encode a node, or for a container just its header, pushing its children
+fn encode_node<'a>(json: &'a Json, out: &mut Vec<u8>, stack: &mut Vec<Children<'a>>) {
+    match json.0 {
+        JsonInner::Null => out.push(TAG_NULL),
+        JsonInner::Bool(false) => out.push(TAG_FALSE),
+        JsonInner::Bool(true) => out.push(TAG_TRUE),
+        JsonInner::Number(ref s) => {
+            out.push(TAG_NUMBER);
+            encode_str(s, out);
+        }
+        JsonInner::String(ref s) => {
+            out.push(TAG_STRING);
+            encode_str(s, out);
+        }
+        JsonInner::Array(ref v) => {
+            out.push(TAG_ARRAY);
+            encode_len(v.len(), out);
+            stack.push(Children::Elements(v.iter()));
+        }
+        JsonInner::Object(ref v) => {
+            out.push(TAG_OBJECT);
+            encode_len(v.len(), out);
+            stack.push(Children::Members(v.iter()));
+        }
+    }
+}
+
+/// Decode a tree from the cache format, rejecting anything malformed
+pub fn decode(data: &[u8]) -> Result<Json, Error> {
+    if !data.starts_with(MAGIC) {
+        return Err(malformed("bad header"));
+    }
+    let mut decoder = Decoder { data: &data[MAGIC.len()..] };
+    let ret = decoder.node()?;
+    if !decoder.data.is_empty() {
+        return Err(malformed("trailing bytes"));
+    }
+    Ok(ret)
+}
+
+fn malformed(what: &str) -> Error {
+    Error(ErrorInner::Other(format!("malformed cache data: {}", what)))
+}
+
+/// The input remaining to be decoded
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.data.len() {
+            return Err(malformed("unexpected end"));
+        }
+        let (ret, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(ret)
+    }
+
+    fn len(&mut self) -> Result<usize, Error> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        let len = u64::from_le_bytes(bytes);
+        // Every element takes at least one byte, so a longer length is bogus
+        if len > self.data.len() as u64 {
+            return Err(malformed("length too long"));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let len = self.len()?;
+        match str::from_utf8(self.take(len)?) {
+            Ok(s) => Ok(s.to_owned()),
+            Err(_) => Err(malformed("invalid UTF-8")),
+        }
+    }
+
+    /// Decode a node and everything in it. Unfinished containers are kept
+    /// on the heap rather than the call stack, so any depth which `encode`
+    /// can write is read back.
+    fn node(&mut self) -> Result<Json, Error> {
+        // Each unfinished container with its count of children still to come
+        let mut stack: Vec<(JsonInner, usize)> = vec![];
+        loop {
+            if let Some(&mut (JsonInner::Object(ref mut v), _)) = stack.last_mut() {
+                let key = self.string()?;
+                v.push((key, Json(JsonInner::Null)));
+            }
+            let (mut inner, mut left) = match self.take(1)?[0] {
+                TAG_NULL => (JsonInner::Null, 0),
+                TAG_FALSE => (JsonInner::Bool(false), 0),
+                TAG_TRUE => (JsonInner::Bool(true), 0),
+                TAG_NUMBER => (JsonInner::Number(self.string()?), 0),
+                TAG_STRING => (JsonInner::String(self.string()?), 0),
+                // Nothing is reserved up front: a length only has to fit in
+                // the remaining input, so nested containers each claiming all
+                // of it would otherwise reserve quadratically much memory
+                TAG_ARRAY => {
+                    let len = self.len()?;
+                    (JsonInner::Array(vec![]), len)
+                }
+                TAG_OBJECT => {
+                    let len = self.len()?;
+                    (JsonInner::Object(vec![]), len)
+                }
+                _ => return Err(malformed("unknown tag")),
+            };
+            // Hand each finished node to its parent, which may finish in turn
+            while left == 0 {
+                let (mut parent, parent_left) = match stack.pop() {
+                    Some(top) => top,
+                    None => return Ok(Json(inner)),
+                };
+                match parent {
+                    JsonInner::Array(ref mut v) => v.push(Json(inner)),
+                    JsonInner::Object(ref mut v) => v.last_mut().unwrap().1 = Json(inner),
+                    _ => unreachable!(),
+                }
+                inner = parent;
+                left = parent_left - 1;
+            }
+            stack.push((inner, left));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Json;
+    use super::{decode, encode};
+
+    #[test]
+    fn test_round_trip() {
+        for s in &[
+            "null", "true", "false", "0", "-1.50e+007", "\"\"", "\"caf\\u00e9 \\\"q\\\"\"", "[]", "{}",
+            "{\"b\": [1, 2.0, {\"x\": null}], \"a\": \"s\", \"b\": false, \"\": [[[]]]}",
+        ] {
+            let json = Json::from_str(s).unwrap();
+            let bytes = encode(&json);
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!(decoded, json);
+            // Number text and member order are kept exactly
+            assert_eq!(decoded.to_string(), json.to_string());
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let bytes = encode(&Json::from_str("{\"key\": [1, \"two\"]}").unwrap());
+        // Every truncation is an error, not a panic
+        for n in 0..bytes.len() {
+            assert!(decode(&bytes[..n]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+        assert!(decode(b"SJC\x01\x07").is_err());
+        assert!(decode(b"XXXX\x00").is_err());
+        // Huge lengths are rejected rather than allocated
+        assert!(decode(b"SJC\x01\x05\xff\xff\xff\xff\xff\xff\xff\xff").is_err());
+        assert!(decode(b"SJC\x01\x04\x02\x00\x00\x00\x00\x00\x00\x00\xff\xfe").is_err());
+        // An object member missing its value
+        assert!(decode(b"SJC\x01\x06\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00").is_err());
+
+        // Nested arrays each claiming as many elements as there are bytes
+        // left are rejected without reserving room for them all
+        let levels = 100_000;
+        let mut nested = b"SJC\x01".to_vec();
+        for i in 0..levels {
+            let left = (levels - i - 1) * 9;
+            nested.push(5);
+            nested.extend_from_slice(&(left as u64).to_le_bytes());
+        }
+        assert!(decode(&nested).is_err());
+    }
+
+    #[test]
+    fn test_deep() {
+        // Deeper than the parser allows by default, so built by hand
+        for &depth in &[200, 1000] {
+            let mut json = Json::from_str("[{\"k\": 1}, {}]").unwrap();
+            for i in 0..depth {
+                json = if i % 2 == 0 {
+                    Json::from(vec![Json::from(true), json, Json::from(Vec::<Json>::new())])
+                } else {
+                    Json::from(vec![("k".to_owned(), json), ("z".to_owned(), Json::from(()))])
+                };
+            }
+            let bytes = encode(&json);
+            assert_eq!(decode(&bytes).unwrap(), json);
+        }
+    }
+}
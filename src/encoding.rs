@@ -0,0 +1,271 @@
+// Stringly-Typed JSON Library for Rust
+// Written in 2015 by
+//   Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Input encoding detection
+//!
+//! The parser reads UTF-8. This module converts input in any of the
+//! encodings RFC 4627 allows, UTF-8, UTF-16 and UTF-32 in either byte
+//! order, into UTF-8 on the fly. The encoding is taken from a byte order
+//! mark if there is one, and otherwise from the pattern of zero bytes at
+//! the start of the input, as in section 3 of the RFC: since the first two
+//! characters of a Json text are ASCII, at least one byte of each is zero
+//! in any encoding but UTF-8.
+//!
+
+use std::{char, io};
+
+/// An encoding of Json text
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    /// UTF-8, the only encoding the parser reads directly
+    Utf8,
+    /// UTF-16, little-endian
+    Utf16Le,
+    /// UTF-16, big-endian
+    Utf16Be,
+    /// UTF-32, little-endian
+    Utf32Le,
+    /// UTF-32, big-endian
+    Utf32Be,
+}
+
+impl Encoding {
+    /// Detects the encoding from the first (up to) four bytes of input,
+    /// returning it and the length of the byte order mark, if any
+    pub fn detect(prefix: &[u8]) -> (Encoding, usize) {
+        let mut b = [None; 4];
+        for (slot, byte) in b.iter_mut().zip(prefix) {
+            *slot = Some(*byte);
+        }
+        let nz = |x: Option<u8>| x.is_some_and(|x| x != 0);
+        match b {
+            [Some(0), Some(0), Some(0xfe), Some(0xff)] => (Encoding::Utf32Be, 4),
+            [Some(0xff), Some(0xfe), Some(0), Some(0)] => (Encoding::Utf32Le, 4),
+            [Some(0xfe), Some(0xff), _, _] => (Encoding::Utf16Be, 2),
+            [Some(0xff), Some(0xfe), _, _] => (Encoding::Utf16Le, 2),
+            [Some(0xef), Some(0xbb), Some(0xbf), _] => (Encoding::Utf8, 3),
+            [Some(0), Some(0), Some(0), x] if nz(x) => (Encoding::Utf32Be, 0),
+            [x, Some(0), Some(0), Some(0)] if nz(x) => (Encoding::Utf32Le, 0),
+            [Some(0), x, _, _] if nz(x) => (Encoding::Utf16Be, 0),
+            [x, Some(0), _, _] if nz(x) => (Encoding::Utf16Le, 0),
+            _ => (Encoding::Utf8, 0),
+        }
+    }
+
+    /// Number of bytes in each code unit
+    fn unit_len(self) -> usize {
+        match self {
+            Encoding::Utf8 => 1,
+            Encoding::Utf16Le | Encoding::Utf16Be => 2,
+            Encoding::Utf32Le | Encoding::Utf32Be => 4,
+        }
+    }
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+/// An iterator over the bytes of input in any supported encoding which
+/// yields the same text as UTF-8, without any byte order mark. It can be
+/// given straight to `parser::Parser::new`.
+pub struct Transcoder<I> {
+    iter: I,
+    encoding: Option<Encoding>,
+    // Bytes read while detecting the encoding but not yet decoded
+    pending: Vec<u8>,
+    // UTF-8 bytes of the last character decoded, and how many are left
+    out: [u8; 4],
+    out_pos: usize,
+    out_len: usize,
+}
+
+impl<I: Iterator<Item=io::Result<u8>>> Transcoder<I> {
+    /// Wrap an input iterator, detecting its encoding from the first bytes
+    pub fn new(iter: I) -> Transcoder<I> {
+        Transcoder {
+            iter,
+            encoding: None,
+            pending: vec![],
+            out: [0; 4],
+            out_pos: 0,
+            out_len: 0,
+        }
+    }
+
+    /// The detected encoding, once any output has been produced
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.encoding
+    }
+
+    /// The next input byte, taking those read during detection first
+    fn next_byte(&mut self) -> Option<io::Result<u8>> {
+        if self.pending.is_empty() {
+            self.iter.next()
+        } else {
+            Some(Ok(self.pending.remove(0)))
+        }
+    }
+
+    /// Read one code unit, or None at a clean end of input
+    fn next_unit(&mut self, encoding: Encoding) -> io::Result<Option<u32>> {
+        let mut bytes = [0u8; 4];
+        let len = encoding.unit_len();
+        for (n, byte) in bytes.iter_mut().take(len).enumerate() {
+            match self.next_byte() {
+                Some(Ok(b)) => *byte = b,
+                Some(Err(e)) => return Err(e),
+                None if n == 0 => return Ok(None),
+                None => return Err(invalid("input ends within a code unit")),
+            }
+        }
+        Ok(Some(match encoding {
+            Encoding::Utf8 => bytes[0] as u32,
+            Encoding::Utf16Le => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+            Encoding::Utf16Be => u16::from_be_bytes([bytes[0], bytes[1]]) as u32,
+            Encoding::Utf32Le => u32::from_le_bytes(bytes),
+            Encoding::Utf32Be => u32::from_be_bytes(bytes),
+        }))
+    }
+
+    /// Decode the next character, or None at the end of input
+    fn next_char(&mut self, encoding: Encoding) -> io::Result<Option<char>> {
+        let unit = match self.next_unit(encoding)? {
+            Some(unit) => unit,
+            None => return Ok(None),
+        };
+        let ch = match encoding {
+            Encoding::Utf16Le | Encoding::Utf16Be if (0xd800..0xdc00).contains(&unit) => {
+                let low = match self.next_unit(encoding)? {
+                    Some(low) if (0xdc00..0xe000).contains(&low) => low,
+                    _ => return Err(invalid("unpaired UTF-16 surrogate")),
+                };
+                char::from_u32(0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00))
+            }
+            _ => char::from_u32(unit),
+        };
+        match ch {
+            Some(ch) => Ok(Some(ch)),
+            None => Err(invalid("invalid code point")),
+        }
+    }
+
+    /// Read enough of the input to detect its encoding, and skip any BOM
+    fn detect(&mut self) -> io::Result<Encoding> {
+        while self.pending.len() < 4 {
+            match self.iter.next() {
+                Some(Ok(b)) => self.pending.push(b),
+                Some(Err(e)) => return Err(e),
+                None => break,
+            }
+        }
+        let (encoding, bom_len) = Encoding::detect(&self.pending);
+        self.pending.drain(..bom_len);
+        self.encoding = Some(encoding);
+        Ok(encoding)
+    }
+}
+
+impl<I: Iterator<Item=io::Result<u8>>> Iterator for Transcoder<I> {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<io::Result<u8>> {
+        if self.out_pos < self.out_len {
+            self.out_pos += 1;
+            return Some(Ok(self.out[self.out_pos - 1]));
+        }
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => match self.detect() {
+                Ok(encoding) => encoding,
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        // UTF-8 passes through untouched; the parser checks it
+        if encoding == Encoding::Utf8 {
+            return self.next_byte();
+        }
+        match self.next_char(encoding) {
+            Ok(Some(ch)) => {
+                self.out_len = ch.encode_utf8(&mut self.out).len();
+                self.out_pos = 1;
+                Some(Ok(self.out[0]))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use Json;
+    use super::{Encoding, Transcoder};
+
+    fn encode(s: &str, encoding: Encoding, bom: bool) -> Vec<u8> {
+        let mut ret = vec![];
+        let text: String = if bom { format!("\u{feff}{}", s) } else { s.to_owned() };
+        match encoding {
+            Encoding::Utf8 => ret.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => for u in text.encode_utf16() { ret.extend_from_slice(&u.to_le_bytes()) },
+            Encoding::Utf16Be => for u in text.encode_utf16() { ret.extend_from_slice(&u.to_be_bytes()) },
+            Encoding::Utf32Le => for c in text.chars() { ret.extend_from_slice(&(c as u32).to_le_bytes()) },
+            Encoding::Utf32Be => for c in text.chars() { ret.extend_from_slice(&(c as u32).to_be_bytes()) },
+        }
+        ret
+    }
+
+    #[test]
+    fn test_detect_and_parse() {
+        let text = "{\"a\": [1, \"x\\u00e9\"], \"b\": {\"c\": null}}";
+        let expected = Json::from_str(text).unwrap();
+        for &encoding in &[Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be, Encoding::Utf32Le, Encoding::Utf32Be] {
+            for &bom in &[false, true] {
+                let bytes = encode(text, encoding, bom);
+                let mut transcoder = Transcoder::new(bytes.iter().map(|b| Ok(*b)));
+                let transcoded: Vec<u8> = transcoder.by_ref().collect::<io::Result<_>>().unwrap();
+                assert_eq!(transcoder.encoding(), Some(encoding));
+                assert_eq!(transcoded, text.as_bytes(), "{:?} {}", encoding, bom);
+                assert_eq!(Json::from_slice_any_encoding(&bytes).unwrap(), expected, "{:?} {}", encoding, bom);
+            }
+        }
+
+        // Short inputs
+        assert_eq!(Json::from_slice_any_encoding(&encode("1", Encoding::Utf16Le, false)).unwrap(), Json::from(1u8));
+        assert_eq!(Json::from_slice_any_encoding(&encode("1", Encoding::Utf16Be, false)).unwrap(), Json::from(1u8));
+        assert_eq!(Json::from_slice_any_encoding(b"7").unwrap(), Json::from(7u8));
+        assert!(Json::from_slice_any_encoding(b"").is_err());
+    }
+
+    #[test]
+    fn test_non_bmp() {
+        let text = "\"\u{1d11e}\"";
+        for &encoding in &[Encoding::Utf16Le, Encoding::Utf16Be, Encoding::Utf32Be] {
+            let bytes = encode(text, encoding, true);
+            let transcoded: Vec<u8> = Transcoder::new(bytes.iter().map(|b| Ok(*b))).collect::<io::Result<_>>().unwrap();
+            assert_eq!(transcoded, text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        // Unpaired surrogate, input ending within a code unit, and a code
+        // point past U+10FFFF
+        for bytes in &[&b"\"\x00\x00\xd8\"\x00"[..], &b"1\x00\x20"[..], &b"\x00\x00\x00\"\x00\x11\x00\x00"[..]] {
+            assert!(Transcoder::new(bytes.iter().map(|b| Ok(*b))).collect::<io::Result<Vec<u8>>>().is_err());
+            assert!(Json::from_slice_any_encoding(bytes).is_err());
+        }
+    }
+}
@@ -31,14 +31,21 @@
 #![cfg_attr(feature = "clippy", allow(match_same_arms))]  // many false positives
 
 extern crate serde;
+#[cfg(feature = "decimal")] extern crate rust_decimal;
+#[cfg(feature = "base64")] extern crate base64;
+#[cfg(feature = "unicode-normalization")] extern crate unicode_normalization;
 #[cfg(test)] extern crate serde_json;
+#[cfg(test)] #[macro_use] extern crate serde_derive;
 
 use serde::{de, ser};
-use std::{error, fmt, io, ops};
+use std::{cmp, error, fmt, hash, io, mem, ops};
 
 pub mod parser;
 pub mod serializer;
 pub mod object;
+#[cfg(feature = "detect_encoding")] pub mod encoding;
+pub mod serde_compat;
+mod cache;
 mod sentinel;
 
 pub use object::{Deserializer, Serializer};
@@ -59,6 +66,24 @@ impl From<parser::Error> for Error {
     }
 }
 
+impl Error {
+    /// The error as a Json object, for tools which display diagnostics; see
+    /// `parser::Error::to_json`. Errors which do not come from the parser
+    /// have a null position and the code `other`.
+    pub fn to_json(&self) -> Json {
+        match self.0 {
+            ErrorInner::Parser(ref e) => e.to_json(),
+            _ => Json(JsonInner::Object(vec![
+                ("line".to_owned(), Json::from(())),
+                ("col".to_owned(), Json::from(())),
+                ("byte_offset".to_owned(), Json::from(())),
+                ("code".to_owned(), Json::from("other")),
+                ("message".to_owned(), Json::from(self.to_string())),
+            ])),
+        }
+    }
+}
+
 impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
         match self.0 {
@@ -125,6 +150,46 @@ enum JsonInner {
     Object(Vec<(String, Json)>)
 }
 
+/// The type of a Json node
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum JsonType {
+    /// A literal "null"
+    Null,
+    /// A boolean
+    Bool,
+    /// A number
+    Number,
+    /// A string
+    String,
+    /// An array
+    Array,
+    /// An object
+    Object,
+}
+
+impl JsonType {
+    /// A short English description of the type, for errors
+    fn describe(self) -> &'static str {
+        match self {
+            JsonType::Null => "null",
+            JsonType::Bool => "a boolean",
+            JsonType::Number => "a number",
+            JsonType::String => "a string",
+            JsonType::Array => "an array",
+            JsonType::Object => "an object",
+        }
+    }
+}
+
+/// One step in the path from the root of a Json tree to one of its nodes
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PathSegment {
+    /// The value for this key of an object
+    Key(String),
+    /// The element at this index of an array
+    Index(usize),
+}
+
 /// A "stringly-typed" Json object. That is, either a value (represented
 /// as a String), or an object (represented as a map from Strings to Jsons).
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -141,9 +206,39 @@ impl Json {
         Json::from_iter(s.bytes().map(Ok))
     }
 
-    /// Construct a Json object from a reader
+    /// Construct a Json object by parsing a byte slice
+    pub fn from_slice(data: &[u8]) -> Result<Json, Error> {
+        Json::from_iter(data.iter().map(|x| Ok(*x)))
+    }
+
+    /// Construct a Json object from a reader. The reader is buffered, so
+    /// input past the end of the value may be read and discarded; use
+    /// `parser::Parser` directly to read several values from one reader.
     pub fn from_reader<R: io::Read>(r: R) -> Result<Json, Error> {
-        Json::from_iter(r.bytes())
+        Json::from_iter(io::Read::bytes(io::BufReader::new(r)))
+    }
+
+    /// Construct a Json object from a reader whose input may be UTF-16 or
+    /// UTF-32 rather than UTF-8, detecting the encoding as described in the
+    /// `encoding` module
+    #[cfg(feature = "detect_encoding")]
+    pub fn from_reader_any_encoding<R: io::Read>(r: R) -> Result<Json, Error> {
+        Json::from_iter(encoding::Transcoder::new(io::Read::bytes(io::BufReader::new(r))))
+    }
+
+    /// Construct a Json object from a byte slice which may be UTF-16 or
+    /// UTF-32 rather than UTF-8, detecting the encoding as described in the
+    /// `encoding` module
+    #[cfg(feature = "detect_encoding")]
+    pub fn from_slice_any_encoding(data: &[u8]) -> Result<Json, Error> {
+        Json::from_iter(encoding::Transcoder::new(data.iter().map(|x| Ok(*x))))
+    }
+
+    /// Iterate over the elements of a Json array read from a reader, parsing
+    /// each element only when it is requested. This allows processing arrays
+    /// too large to hold in memory. The iterator stops after the first error.
+    pub fn array_elements_from_reader<R: io::Read>(r: R) -> parser::ArrayElements<io::Bytes<io::BufReader<R>>> {
+        parser::ArrayElements::new(io::Read::bytes(io::BufReader::new(r)))
     }
 
     /// Converts something serializable to a Json object
@@ -151,6 +246,113 @@ impl Json {
         val.serialize(Serializer::new())
     }
 
+    /// Construct an empty object, e.g. to fill in with `with`
+    pub fn empty_object() -> Json {
+        Json(JsonInner::Object(vec![]))
+    }
+
+    /// Construct an empty array, e.g. to fill in with `with_pushed`
+    pub fn empty_array() -> Json {
+        Json(JsonInner::Array(vec![]))
+    }
+
+    /// Sets the member `key` of this object to `value`, replacing the value
+    /// of the first member with that key or else adding a member at the
+    /// end, and returns the object, for building objects by chaining:
+    /// `Json::empty_object().with("a", 1).with("b", "x")`.
+    ///
+    /// Panics if this is not an object.
+    pub fn with<K: Into<String>, V: Into<Json>>(mut self, key: K, value: V) -> Json {
+        if let JsonInner::Object(ref mut v) = self.0 {
+            let key = key.into();
+            let value = value.into();
+            match v.iter_mut().find(|m| m.0 == key) {
+                Some(member) => member.1 = value,
+                None => v.push((key, value)),
+            }
+        } else {
+            panic!("Tried to set a member of a non-object Json object!");
+        }
+        self
+    }
+
+    /// Appends `value` to this array and returns the array, for building
+    /// arrays by chaining.
+    ///
+    /// Panics if this is not an array.
+    pub fn with_pushed<V: Into<Json>>(mut self, value: V) -> Json {
+        if let JsonInner::Array(ref mut v) = self.0 {
+            v.push(value.into());
+        } else {
+            panic!("Tried to push onto a non-array Json object!");
+        }
+        self
+    }
+
+    /// Checks this against a shape, a lightweight schema which is itself a
+    /// Json document:
+    ///
+    /// * the strings `"null"`, `"bool"`, `"number"`, `"string"`, `"array"`
+    ///   and `"object"` match any value of that type, and `"any"` matches
+    ///   anything;
+    /// * an object matches objects which have every one of its keys, with
+    ///   values matching the corresponding shapes; other keys are allowed;
+    /// * an empty array matches any array, and a non-empty array matches
+    ///   arrays each of whose elements matches one of its shapes.
+    ///
+    /// Any other shape matches nothing.
+    pub fn matches_shape(&self, shape: &Json) -> bool {
+        match shape.0 {
+            JsonInner::String(ref name) => match &name[..] {
+                "any" => true,
+                "null" => self.kind() == JsonType::Null,
+                "bool" => self.kind() == JsonType::Bool,
+                "number" => self.kind() == JsonType::Number,
+                "string" => self.kind() == JsonType::String,
+                "array" => self.kind() == JsonType::Array,
+                "object" => self.kind() == JsonType::Object,
+                _ => false,
+            },
+            JsonInner::Object(ref members) => {
+                self.object().is_some() && members.iter().all(|(key, shape)| {
+                    self.get(key).is_some_and(|val| val.matches_shape(shape))
+                })
+            }
+            JsonInner::Array(ref shapes) => match self.array() {
+                Some(v) => shapes.is_empty() || v.iter().all(|elem| shapes.iter().any(|s| elem.matches_shape(s))),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the type shared by all elements, if this is a non-empty
+    /// array whose elements are all of the same type. Only the elements
+    /// themselves are compared, so `[[1], ["a"]]` is an array of arrays.
+    pub fn is_homogeneous_array(&self) -> Option<JsonType> {
+        let v = self.array()?;
+        let kind = v.first()?.kind();
+        if v.iter().all(|elem| elem.kind() == kind) { Some(kind) } else { None }
+    }
+
+    /// Counts the nodes of each type in the tree, including this one,
+    /// returning the counts indexed by `JsonType as usize`, i.e. in the
+    /// order null, bool, number, string, array, object. Object keys are not
+    /// counted. Works without recursion, so any depth is fine.
+    pub fn count_by_type(&self) -> [usize; 6] {
+        let mut ret = [0; 6];
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            ret[node.kind() as usize] += 1;
+            match node.0 {
+                JsonInner::Array(ref v) => stack.extend(v),
+                JsonInner::Object(ref v) => stack.extend(v.iter().map(|m| &m.1)),
+                _ => {}
+            }
+        }
+        ret
+    }
+
     /// Returns a null, if this is a null
     pub fn null(&self) -> Option<()> { if let JsonInner::Null = self.0 { Some(()) } else { None } }
     /// Returns the value, if this is a boolean
@@ -164,7 +366,409 @@ impl Json {
     /// Returns the value, if this is an object
     pub fn object(&self) -> Option<&[(String, Json)]> { if let JsonInner::Object(ref x) = self.0 { Some(&x[..]) } else { None } }
 
-    /// Obtain a reference to a specified member, if this is an object
+    /// Returns the value, if this is a boolean; the same as `bool`
+    pub fn as_bool(&self) -> Option<bool> { self.bool() }
+    /// Returns the value, if this is a string; the same as `string`
+    pub fn as_str(&self) -> Option<&str> { self.string() }
+    /// Returns the elements, if this is an array; the same as `array`
+    pub fn as_array(&self) -> Option<&[Json]> { self.array() }
+    /// Returns the members, if this is an object; the same as `object`
+    pub fn as_object(&self) -> Option<&[(String, Json)]> { self.object() }
+    /// Returns the text of the number as parsed, if this is a number; the
+    /// same as `num`
+    pub fn as_number_str(&self) -> Option<&str> { self.num() }
+
+    /// Returns the value if this is a string, or else `default`
+    pub fn as_str_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.string().unwrap_or(default)
+    }
+
+    /// Returns the type of this node
+    pub fn kind(&self) -> JsonType {
+        match self.0 {
+            JsonInner::Null => JsonType::Null,
+            JsonInner::Bool(_) => JsonType::Bool,
+            JsonInner::Number(_) => JsonType::Number,
+            JsonInner::String(_) => JsonType::String,
+            JsonInner::Array(_) => JsonType::Array,
+            JsonInner::Object(_) => JsonType::Object,
+        }
+    }
+
+    /// Returns true if this is a null
+    pub fn is_null(&self) -> bool { self.kind() == JsonType::Null }
+    /// Returns true if this is a boolean
+    pub fn is_bool(&self) -> bool { self.kind() == JsonType::Bool }
+    /// Returns true if this is a number. Strings are not numbers, even if
+    /// their contents look like one.
+    pub fn is_number(&self) -> bool { self.kind() == JsonType::Number }
+    /// Returns true if this is a string
+    pub fn is_string(&self) -> bool { self.kind() == JsonType::String }
+    /// Returns true if this is an array
+    pub fn is_array(&self) -> bool { self.kind() == JsonType::Array }
+    /// Returns true if this is an object
+    pub fn is_object(&self) -> bool { self.kind() == JsonType::Object }
+
+    /// Returns true if this is a null, boolean, number or string
+    pub fn is_scalar(&self) -> bool { !self.is_container() }
+    /// Returns true if this is an array or object
+    pub fn is_container(&self) -> bool { matches!(self.kind(), JsonType::Array | JsonType::Object) }
+
+    /// Returns the contents, if this is a string, or else gives back the
+    /// original Json object. There is no `TryFrom<Json> for String`, since
+    /// `String: From<Json>` serializes instead.
+    pub fn into_string(self) -> Result<String, Json> {
+        match self.0 {
+            JsonInner::String(s) => Ok(s),
+            x => Err(Json(x)),
+        }
+    }
+
+    /// Returns the elements, if this is an array, or else gives back the
+    /// original Json object
+    pub fn into_vec(self) -> Result<Vec<Json>, Json> {
+        match self.0 {
+            JsonInner::Array(v) => Ok(v),
+            x => Err(Json(x)),
+        }
+    }
+
+    /// Returns the members, if this is an object, or else gives back the
+    /// original Json object
+    pub fn into_object(self) -> Result<Vec<(String, Json)>, Json> {
+        match self.0 {
+            JsonInner::Object(v) => Ok(v),
+            x => Err(Json(x)),
+        }
+    }
+
+    /// Returns the value parsed as a float, or NaN if this is not a number or
+    /// could not be parsed as one. This is lossy: besides the usual rounding
+    /// of large or precise numbers, failures are indistinguishable from an
+    /// actual NaN. Use it for display code, not for validation.
+    pub fn as_f64_lossy(&self) -> f64 {
+        self.num().and_then(|s| s.parse().ok()).unwrap_or(f64::NAN)
+    }
+
+    /// Returns the value parsed as a float, if this is a number whose
+    /// magnitude fits in an `f64`. Precise numbers are rounded to the
+    /// nearest `f64`; numbers too large give None rather than infinity.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.num()?.parse().ok().filter(|x: &f64| x.is_finite())
+    }
+
+    /// Returns the value as an `i64`, if this is a number with an integral
+    /// value in range. Unlike `as_i128`, this looks at the value rather than
+    /// how it is written, so `1.0` and `1.5e3` are accepted, exactly, while
+    /// `1.5` and `1e-3` give None.
+    pub fn as_i64(&self) -> Option<i64> {
+        integer_digits(self.num()?, 20)?.parse().ok()
+    }
+
+    /// Returns the value as a `u64`, if this is a number with a
+    /// non-negative integral value in range, as with `as_i64`
+    pub fn as_u64(&self) -> Option<u64> {
+        integer_digits(self.num()?, 20)?.parse().ok()
+    }
+
+    /// Whether this is a number written as an integer, without a fraction
+    /// or exponent. This looks only at the text, so `5.0` is not an integer.
+    pub fn number_is_integer(&self) -> bool {
+        self.num().is_some_and(|s| !s.contains(['.', 'e', 'E']))
+    }
+
+    /// Whether this is a number written with a fraction or exponent, such
+    /// as `5.0` or `5e2`
+    pub fn number_is_float(&self) -> bool {
+        self.num().is_some_and(|s| s.contains(['.', 'e', 'E']))
+    }
+
+    /// Returns the value as an `i128`, if this is a number written as an
+    /// integer in range. Since numbers are kept as text this is exact, even
+    /// beyond 64 bits. Fractions and exponents, even `1.0` or `1e3`, give
+    /// None.
+    pub fn as_i128(&self) -> Option<i128> {
+        self.num().and_then(|s| s.parse().ok())
+    }
+
+    /// Returns the value as a `u128`, if this is a number written as a
+    /// non-negative integer in range. As with `as_i128`, this is exact.
+    pub fn as_u128(&self) -> Option<u128> {
+        self.num().and_then(|s| s.parse().ok())
+    }
+
+    /// Returns the bytes, if this is an array whose elements are all
+    /// integers from 0 to 255, as some Json encodes binary data
+    pub fn as_byte_vec(&self) -> Option<Vec<u8>> {
+        self.array()?.iter().map(|elem| elem.num().and_then(|s| s.parse().ok())).collect()
+    }
+
+    /// Returns the decoded bytes, if this is a string of standard base64
+    /// with padding, as many APIs use for binary data
+    #[cfg(feature = "base64")]
+    pub fn as_base64_bytes(&self) -> Option<Vec<u8>> {
+        use base64::Engine;
+
+        base64::engine::general_purpose::STANDARD.decode(self.string()?).ok()
+    }
+
+    /// Returns the elements as floats, if this is an array of numbers,
+    /// integers and floats alike. Large or precise numbers are rounded to
+    /// the nearest `f64`, as by `as_f64_lossy`.
+    pub fn as_f64_vec(&self) -> Option<Vec<f64>> {
+        self.array()?.iter().map(|elem| elem.num().and_then(|s| s.parse().ok())).collect()
+    }
+
+    /// Returns the sum of an array of numbers, which is 0 for an empty
+    /// array, or None if this is not an array or has a non-number element
+    pub fn sum_f64(&self) -> Option<f64> {
+        self.as_f64_vec().map(|v| v.iter().sum())
+    }
+
+    /// Returns the smallest of an array of numbers, or None if this is not
+    /// an array, is empty or has a non-number element
+    pub fn min_f64(&self) -> Option<f64> {
+        self.as_f64_vec().and_then(|v| v.into_iter().reduce(f64::min))
+    }
+
+    /// Returns the largest of an array of numbers, or None if this is not
+    /// an array, is empty or has a non-number element
+    pub fn max_f64(&self) -> Option<f64> {
+        self.as_f64_vec().and_then(|v| v.into_iter().reduce(f64::max))
+    }
+
+    /// Folds the elements of an array with a function which may fail,
+    /// stopping at the first error. Returns None if this is not an array,
+    /// since no error of the caller's type is available to report that.
+    pub fn try_fold<B, E, F>(&self, init: B, f: F) -> Option<Result<B, E>>
+        where F: FnMut(B, &Json) -> Result<B, E>
+    {
+        self.array().map(|v| v.iter().try_fold(init, f))
+    }
+
+    /// Rewrites every number in the tree into a canonical form: the shortest
+    /// decimal representation which reads back as the same `f64`, using an
+    /// exponent only for magnitudes of at least 1e21 or below 1e-6. So `1.0`
+    /// becomes `1` and `1E2` becomes `100`. Non-numbers are untouched.
+    ///
+    /// Since this goes through `f64` it is lossy: integers beyond 2^53 and
+    /// anything with more than about 17 significant digits are rounded.
+    /// Numbers too large for an `f64` are left as they are. See
+    /// `coerce_numbers_decimal` for a more precise alternative.
+    pub fn coerce_numbers(&mut self) {
+        self.map_numbers(&mut canonical_number);
+    }
+
+    /// Rewrites every number in the tree into a canonical decimal form, with
+    /// no exponent and no trailing zeros after the decimal point. This keeps
+    /// up to 28 significant digits; numbers outside the range of a 96-bit
+    /// decimal are left as they are.
+    #[cfg(feature = "decimal")]
+    pub fn coerce_numbers_decimal(&mut self) {
+        use std::str::FromStr;
+        use rust_decimal::Decimal;
+
+        self.map_numbers(&mut |s| {
+            let dec = if s.contains(['e', 'E']) {
+                Decimal::from_scientific(s)
+            } else {
+                Decimal::from_str(s)
+            };
+            dec.ok().map(|d| d.normalize().to_string())
+        });
+    }
+
+    /// Replace each number with the result of `f`, if it returns something
+    fn map_numbers<F: FnMut(&str) -> Option<String>>(&mut self, f: &mut F) {
+        match self.0 {
+            JsonInner::Number(ref mut s) => {
+                if let Some(new) = f(s) {
+                    *s = new;
+                }
+            }
+            JsonInner::Array(ref mut v) => {
+                for elem in v {
+                    elem.map_numbers(f);
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                for (_, val) in v {
+                    val.map_numbers(f);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Calls `f` on every node of the tree, along with the path to it from
+    /// this node. Each node is visited before its children, so if `f`
+    /// replaces a node, the replacement's children are visited instead.
+    pub fn walk_mut<F: FnMut(&[PathSegment], &mut Json)>(&mut self, mut f: F) {
+        self.walk_mut_from(&mut vec![], &mut f);
+    }
+
+    fn walk_mut_from<F: FnMut(&[PathSegment], &mut Json)>(&mut self, path: &mut Vec<PathSegment>, f: &mut F) {
+        f(path, self);
+        match self.0 {
+            JsonInner::Array(ref mut v) => {
+                for (n, elem) in v.iter_mut().enumerate() {
+                    path.push(PathSegment::Index(n));
+                    elem.walk_mut_from(path, f);
+                    path.pop();
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                for (key, val) in v {
+                    path.push(PathSegment::Key(key.clone()));
+                    val.walk_mut_from(path, f);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Renames object keys throughout the tree, replacing each key with the
+    /// result of `f` if it returns something. `f` is called once for every
+    /// key, including those of objects nested anywhere. Members keep their
+    /// positions, so if a new key collides with an existing one the object
+    /// simply ends up with both, just as when parsing input with duplicate
+    /// keys.
+    pub fn rename_keys<F: FnMut(&str) -> Option<String>>(&mut self, mut f: F) {
+        self.rename_keys_with(&mut f);
+    }
+
+    fn rename_keys_with<F: FnMut(&str) -> Option<String>>(&mut self, f: &mut F) {
+        match self.0 {
+            JsonInner::Array(ref mut v) => {
+                for elem in v {
+                    elem.rename_keys_with(f);
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                for (key, val) in v {
+                    if let Some(new) = f(key) {
+                        *key = new;
+                    }
+                    val.rename_keys_with(f);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Removes every object member whose value is null, throughout the
+    /// tree. Nulls which are array elements are kept, since removing them
+    /// would shift the positions of later elements. Objects and arrays left
+    /// empty are kept too.
+    pub fn drop_null_fields(&mut self) {
+        match self.0 {
+            JsonInner::Array(ref mut v) => {
+                for elem in v {
+                    elem.drop_null_fields();
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                v.retain(|(_, val)| val.null().is_none());
+                for (_, val) in v {
+                    val.drop_null_fields();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Removes every object member and array element whose value is an
+    /// empty object or array, throughout the tree. Children are pruned
+    /// first, so a container holding only empty containers is removed as
+    /// well. The value itself is left in place even if it ends up empty,
+    /// since it has no parent to be removed from.
+    pub fn prune_empty(&mut self) {
+        fn is_empty(json: &Json) -> bool {
+            match json.0 {
+                JsonInner::Array(ref v) => v.is_empty(),
+                JsonInner::Object(ref v) => v.is_empty(),
+                _ => false,
+            }
+        }
+
+        match self.0 {
+            JsonInner::Array(ref mut v) => {
+                for elem in v.iter_mut() {
+                    elem.prune_empty();
+                }
+                v.retain(|elem| !is_empty(elem));
+            }
+            JsonInner::Object(ref mut v) => {
+                for (_, val) in v.iter_mut() {
+                    val.prune_empty();
+                }
+                v.retain(|(_, val)| !is_empty(val));
+            }
+            _ => {}
+        }
+    }
+
+    /// Converts every string and object key in the tree to Unicode
+    /// Normalization Form C, so that text which looks the same compares
+    /// equal, e.g. `e` followed by a combining acute accent becomes `é`.
+    /// Keys which differed only in normalization end up as duplicates.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn normalize_nfc(&mut self) {
+        use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+        fn normalize(s: &mut String) {
+            if !is_nfc(s) {
+                *s = s.nfc().collect();
+            }
+        }
+
+        match self.0 {
+            JsonInner::String(ref mut s) => normalize(s),
+            JsonInner::Array(ref mut v) => {
+                for elem in v {
+                    elem.normalize_nfc();
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                for (key, val) in v {
+                    normalize(key);
+                    val.normalize_nfc();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Shortens every string in the tree which has more than `max_len`
+    /// characters to its first `max_len`, followed by `…` to mark the cut,
+    /// e.g. before logging a document. Object keys are left intact, so the
+    /// structure is unchanged.
+    pub fn truncate_strings(&mut self, max_len: usize) {
+        match self.0 {
+            JsonInner::String(ref mut s) => {
+                if let Some((idx, _)) = s.char_indices().nth(max_len) {
+                    s.truncate(idx);
+                    s.push('\u{2026}');
+                }
+            }
+            JsonInner::Array(ref mut v) => {
+                for elem in v {
+                    elem.truncate_strings(max_len);
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                for (_, val) in v {
+                    val.truncate_strings(max_len);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Obtain a reference to a specified member, if this is an object. If
+    /// the key appears more than once, as the parser allows, this is the
+    /// first; see `get_all`.
     pub fn get(&self, index: &str) -> Option<&Json> {
         if let JsonInner::Object(ref v) = self.0 {
             for &(ref key, ref obj) in v {
@@ -176,6 +780,425 @@ impl Json {
         None
     }
 
+    /// Iterate over the values of every member with the given key, in
+    /// order, for objects with duplicate keys. This is empty if this is
+    /// not an object or has no such member.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item=&'a Json> + 'a {
+        self.object().unwrap_or(&[]).iter().filter(move |m| m.0 == key).map(|m| &m.1)
+    }
+
+    /// Obtain a reference to a specified element, if this is an array long
+    /// enough to have it. This is the non-panicking form of `json[index]`.
+    pub fn get_index(&self, index: usize) -> Option<&Json> {
+        self.array().and_then(|v| v.get(index))
+    }
+
+    /// Obtain a reference to a specified member if this is an object which
+    /// has it, or else to `default`
+    pub fn get_or<'a>(&'a self, key: &str, default: &'a Json) -> &'a Json {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Returns the value unchanged if it is an object, or an error naming
+    /// what was found instead. Useful for validating request bodies.
+    pub fn require_object(self) -> Result<Json, Error> {
+        if let JsonInner::Object(_) = self.0 {
+            Ok(self)
+        } else {
+            Err(Error(ErrorInner::Other(format!("expected an object, found {}", self.describe_type()))))
+        }
+    }
+
+    /// Returns the value unchanged if it is an array, or an error naming
+    /// what was found instead.
+    pub fn require_array(self) -> Result<Json, Error> {
+        if let JsonInner::Array(_) = self.0 {
+            Ok(self)
+        } else {
+            Err(Error(ErrorInner::Other(format!("expected an array, found {}", self.describe_type()))))
+        }
+    }
+
+    /// Returns an error if arrays and objects are nested more than `max`
+    /// levels deep, counting as the parser's depth limit does: a scalar has
+    /// depth 0 and `[]` has depth 1. Useful for values built other than by
+    /// parsing, or parsed with a looser limit. Works without recursion, so
+    /// checking is safe however deep the value is.
+    pub fn ensure_depth(&self, max: usize) -> Result<(), Error> {
+        if self.exceeds_depth(max) {
+            Err(Error(ErrorInner::Other(format!("nesting depth limit of {} exceeded", max))))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn exceeds_depth(&self, max: usize) -> bool {
+        // Each node with how many more levels of nesting it may contain,
+        // kept on a stack so that checking does not recurse
+        let mut stack = vec![(self, max)];
+        while let Some((node, left)) = stack.pop() {
+            match node.0 {
+                JsonInner::Array(_) | JsonInner::Object(_) if left == 0 => return true,
+                JsonInner::Array(ref v) => stack.extend(v.iter().map(|elem| (elem, left - 1))),
+                JsonInner::Object(ref v) => stack.extend(v.iter().map(|m| (&m.1, left - 1))),
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Merges two arrays of objects, as records identified by the value of
+    /// their `key` member. Each record of `b` whose key matches a record of
+    /// `a` is deep-merged into the first such record, and the rest are
+    /// appended; records of `a` are kept in order. Records without the key
+    /// never match. When merging, objects are merged member by member and
+    /// anything else in `b`, including arrays, replaces what is in `a`.
+    ///
+    /// Returns an error if either input is not an array of objects.
+    pub fn merge_arrays_by_key(a: &Json, b: &Json, key: &str) -> Result<Json, Error> {
+        let a = a.require_records()?;
+        let b = b.require_records()?;
+
+        let mut ret = a.to_vec();
+        for record in b {
+            let id = record.get(key);
+            let existing = ret[..a.len()].iter_mut().find(|r| id.is_some() && r.get(key) == id);
+            match existing {
+                Some(existing) => existing.deep_merge(record),
+                None => ret.push(record.clone()),
+            }
+        }
+        Ok(Json(JsonInner::Array(ret)))
+    }
+
+    /// Returns the elements, if this is an array of objects
+    fn require_records(&self) -> Result<&[Json], Error> {
+        let v = match self.0 {
+            JsonInner::Array(ref v) => v,
+            _ => return Err(Error(ErrorInner::Other(format!("expected an array, found {}", self.describe_type())))),
+        };
+        for elem in v {
+            if let JsonInner::Object(_) = elem.0 {} else {
+                return Err(Error(ErrorInner::Other(format!("expected an array of objects, found {}", elem.describe_type()))));
+            }
+        }
+        Ok(v)
+    }
+
+    /// Merges `other` into this object, recursing into members which are
+    /// objects on both sides and otherwise letting `other` win
+    fn deep_merge(&mut self, other: &Json) {
+        if let (JsonInner::Object(v), JsonInner::Object(other)) = (&mut self.0, &other.0) {
+            for (key, val) in other {
+                match v.iter_mut().find(|m| m.0 == *key) {
+                    Some(member) => member.1.deep_merge(val),
+                    None => v.push((key.clone(), val.clone())),
+                }
+            }
+            return;
+        }
+        *self = other.clone();
+    }
+
+    /// Fills in members missing from this object with those of `defaults`,
+    /// recursing into members which are objects on both sides. Members
+    /// already present are kept, even if null, and new members are added at
+    /// the end in the order of `defaults`. Arrays are values like any other:
+    /// they are never merged element by element, and a default array only
+    /// applies if the member is missing entirely. Nothing happens unless
+    /// both are objects.
+    pub fn merge_defaults(&mut self, defaults: &Json) {
+        if let (JsonInner::Object(v), JsonInner::Object(defaults)) = (&mut self.0, &defaults.0) {
+            for (key, default) in defaults {
+                match v.iter_mut().find(|m| m.0 == *key) {
+                    Some(member) => member.1.merge_defaults(default),
+                    None => v.push((key.clone(), default.clone())),
+                }
+            }
+        }
+    }
+
+    /// A short English description of the type of this object, for errors
+    fn describe_type(&self) -> &'static str {
+        self.kind().describe()
+    }
+
+    /// Obtain a reference to a member of a member, if both levels are objects
+    pub fn get2(&self, k1: &str, k2: &str) -> Option<&Json> {
+        self.get(k1).and_then(|obj| obj.get(k2))
+    }
+
+    /// Obtain a reference to a member three levels down, if all are objects
+    pub fn get3(&self, k1: &str, k2: &str, k3: &str) -> Option<&Json> {
+        self.get2(k1, k2).and_then(|obj| obj.get(k3))
+    }
+
+    /// Looks up a value by an RFC 6901 Json Pointer such as `/a/0/b`, where
+    /// `~1` stands for `/` and `~0` for `~` within a key. The empty pointer
+    /// refers to the whole document. With duplicate keys the first is used.
+    pub fn pointer(&self, pointer: &str) -> Option<&Json> {
+        let mut target = self;
+        for token in pointer_tokens(pointer)? {
+            target = target.child(&token)?;
+        }
+        Some(target)
+    }
+
+    /// Looks up an object member or array element by a pointer token
+    fn child(&self, token: &str) -> Option<&Json> {
+        match self.0 {
+            JsonInner::Object(_) => self.get(token),
+            JsonInner::Array(ref v) => v.get(pointer_index(token)?),
+            _ => None,
+        }
+    }
+
+    /// Looks up a value by an RFC 6901 Json Pointer as `pointer` does,
+    /// returning a mutable reference
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Json> {
+        let mut target = self;
+        for token in pointer_tokens(pointer)? {
+            target = target.child_mut(&token)?;
+        }
+        Some(target)
+    }
+
+    fn child_mut(&mut self, token: &str) -> Option<&mut Json> {
+        match self.0 {
+            JsonInner::Object(ref mut v) => v.iter_mut().find(|m| m.0 == token).map(|m| &mut m.1),
+            JsonInner::Array(ref mut v) => v.get_mut(pointer_index(token)?),
+            _ => None,
+        }
+    }
+
+    /// Replaces the value at an RFC 6901 Json Pointer, returning the value
+    /// which was there. If the pointer does not resolve, nothing is changed
+    /// and an error is returned; nothing is ever added.
+    pub fn replace_pointer(&mut self, pointer: &str, value: Json) -> Result<Json, Error> {
+        match self.pointer_mut(pointer) {
+            Some(target) => Ok(mem::replace(target, value)),
+            None => Err(Error(ErrorInner::Other(format!("pointer `{}` does not resolve", pointer)))),
+        }
+    }
+
+    /// Returns a copy containing only the nodes at the given RFC 6901 Json
+    /// Pointers, along with their ancestors. Array elements keep their
+    /// indices, so those before a kept element which are not themselves
+    /// kept are replaced with null. Pointers which do not resolve are
+    /// ignored. If nothing is kept the result is an empty array or object,
+    /// or null if this is neither.
+    pub fn project(&self, pointers: &[&str]) -> Json {
+        let paths: Vec<Vec<String>> = pointers.iter()
+            .filter(|p| self.pointer(p).is_some())
+            .filter_map(|p| pointer_tokens(p))
+            .collect();
+        let paths: Vec<&[String]> = paths.iter().map(|p| &p[..]).collect();
+        self.project_paths(&paths)
+    }
+
+    /// Project onto paths which are all known to resolve
+    fn project_paths(&self, paths: &[&[String]]) -> Json {
+        if paths.iter().any(|p| p.is_empty()) {
+            return self.clone();
+        }
+        // The paths which continue through the given token
+        let below = |token: &str| -> Vec<&[String]> {
+            paths.iter().filter(|p| p[0] == token).map(|p| &p[1..]).collect()
+        };
+        match self.0 {
+            JsonInner::Object(ref v) => {
+                let mut ret: Vec<(String, Json)> = vec![];
+                for (key, val) in v {
+                    // Pointers only reach the first member with a given key
+                    if ret.iter().any(|m| m.0 == *key) {
+                        continue;
+                    }
+                    let rest = below(key);
+                    if !rest.is_empty() {
+                        ret.push((key.clone(), val.project_paths(&rest)));
+                    }
+                }
+                Json(JsonInner::Object(ret))
+            }
+            JsonInner::Array(ref v) => {
+                let mut ret = vec![];
+                for (n, elem) in v.iter().enumerate() {
+                    let rest = below(&n.to_string());
+                    if !rest.is_empty() {
+                        ret.resize(n, Json(JsonInner::Null));
+                        ret.push(elem.project_paths(&rest));
+                    }
+                }
+                Json(JsonInner::Array(ret))
+            }
+            _ => Json(JsonInner::Null),
+        }
+    }
+
+    /// Returns an RFC 6901 Json Pointer to every object member named `key`,
+    /// at any depth, in the order they appear in the document
+    pub fn paths_to_key(&self, key: &str) -> Vec<String> {
+        let mut ret = vec![];
+        self.paths_to_key_from(key, &mut String::new(), &mut ret);
+        ret
+    }
+
+    fn paths_to_key_from(&self, key: &str, path: &mut String, ret: &mut Vec<String>) {
+        let len = path.len();
+        match self.0 {
+            JsonInner::Array(ref v) => {
+                for (n, elem) in v.iter().enumerate() {
+                    path.push('/');
+                    path.push_str(&n.to_string());
+                    elem.paths_to_key_from(key, path, ret);
+                    path.truncate(len);
+                }
+            }
+            JsonInner::Object(ref v) => {
+                for (k, val) in v {
+                    path.push('/');
+                    path.push_str(&k.replace('~', "~0").replace('/', "~1"));
+                    if k == key {
+                        ret.push(path.clone());
+                    }
+                    val.paths_to_key_from(key, path, ret);
+                    path.truncate(len);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the members of an object sorted by key, without reordering
+    /// the object itself. Duplicate keys keep their relative order. Returns
+    /// an empty vector if this is not an object.
+    pub fn sorted_entries(&self) -> Vec<(&str, &Json)> {
+        let mut ret = vec![];
+        if let JsonInner::Object(ref v) = self.0 {
+            ret.extend(v.iter().map(|(key, obj)| (&key[..], obj)));
+            ret.sort_by(|a, b| a.0.cmp(b.0));
+        }
+        ret
+    }
+
+    /// Whether this node, or any node nested within it, is equal to `needle`.
+    /// Numbers are compared by their text, so `1` does not match `1.0`.
+    pub fn contains_value(&self, needle: &Json) -> bool {
+        if self == needle {
+            return true;
+        }
+        match self.0 {
+            JsonInner::Array(ref v) => v.iter().any(|elem| elem.contains_value(needle)),
+            JsonInner::Object(ref v) => v.iter().any(|(_, val)| val.contains_value(needle)),
+            _ => false,
+        }
+    }
+
+    /// Whether this node, or any node nested within it, is an object with a
+    /// member named `key`
+    pub fn contains_key(&self, key: &str) -> bool {
+        match self.0 {
+            JsonInner::Array(ref v) => v.iter().any(|elem| elem.contains_key(key)),
+            JsonInner::Object(ref v) => v.iter().any(|(k, val)| k == key || val.contains_key(key)),
+            _ => false,
+        }
+    }
+
+    /// Sorts the members of every object in the tree by key, comparing keys
+    /// bytewise. Members with equal keys keep their relative order.
+    pub fn sort_keys(&mut self) {
+        self.sort_keys_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    /// Sorts the members of every object in the tree with the comparator
+    /// `cmp`, which is given whole members so it can order by value as well
+    /// as by key. The sort is stable. Each object is sorted before the
+    /// objects nested in it.
+    pub fn sort_keys_by<F>(&mut self, mut cmp: F)
+        where F: FnMut(&(String, Json), &(String, Json)) -> cmp::Ordering
+    {
+        self.sort_keys_with(&mut cmp);
+    }
+
+    fn sort_keys_with<F>(&mut self, cmp: &mut F)
+        where F: FnMut(&(String, Json), &(String, Json)) -> cmp::Ordering
+    {
+        match self.0 {
+            JsonInner::Array(ref mut v) => {
+                for elem in v {
+                    elem.sort_keys_with(cmp);
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                v.sort_by(|a, b| cmp(a, b));
+                for (_, val) in v {
+                    val.sort_keys_with(cmp);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Puts every object in the tree into canonical form: members sorted by
+    /// key, as with `sort_keys`, and duplicate keys removed, keeping the last
+    /// value as most parsers do. Returns the number of changes made, which
+    /// is the number of objects reordered plus the number of members
+    /// removed; zero means the document was already canonical.
+    pub fn canonicalize_in_place(&mut self) -> usize {
+        let mut changes = 0;
+        match self.0 {
+            JsonInner::Array(ref mut v) => {
+                for elem in v {
+                    changes += elem.canonicalize_in_place();
+                }
+            }
+            JsonInner::Object(ref mut v) => {
+                if !v.windows(2).all(|w| w[0].0 <= w[1].0) {
+                    v.sort_by(|a, b| a.0.cmp(&b.0));
+                    changes += 1;
+                }
+                // Sorting is stable, so the last of each run of equal keys is
+                // the last in the original order; `dedup_by` keeps the first
+                let len = v.len();
+                v.reverse();
+                v.dedup_by(|a, b| a.0 == b.0);
+                v.reverse();
+                changes += len - v.len();
+
+                for (_, val) in v {
+                    changes += val.canonicalize_in_place();
+                }
+            }
+            _ => {}
+        }
+        changes
+    }
+
+    /// Feeds the canonical form of the object into a hasher. Keys are sorted
+    /// and duplicates removed as by `canonicalize_in_place`, and the result
+    /// is serialized with the default layout and every non-ASCII character
+    /// escaped, whichever features are enabled. Documents which differ only
+    /// in key order, formatting or overridden duplicate keys hash
+    /// identically. Numbers are hashed as written, so `1` and `1.0` differ.
+    pub fn canonical_hash<H: hash::Hasher>(&self, h: &mut H) {
+        struct HashWriter<'a, H: 'a>(&'a mut H);
+
+        impl<'a, H: hash::Hasher> io::Write for HashWriter<'a, H> {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.write(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> { Ok(()) }
+        }
+
+        let mut canonical = self.clone();
+        canonical.canonicalize_in_place();
+        // Escaping everything keeps the bytes hashed independent of `utf16`
+        let mut writer = serializer::Writer::new();
+        writer.escape_non_ascii(true);
+        writer.write(&canonical, &mut HashWriter(h)).expect("hashing cannot fail");
+    }
+
     /// Return the number of subobjects this object represents
     /// (so a count for Arrays and Objects). NOT a string length.
     pub fn len(&self) -> usize {
@@ -201,24 +1224,274 @@ impl Json {
         }
     }
 
-    /// Reserialize the object into a writer
+    /// The number of bytes this takes when serialized, including quotes
+    /// and escapes, if it is a string. This can differ a lot from the
+    /// length of the string itself; with the `utf16` feature, for example,
+    /// each non-ASCII character is written as one or two `\uXXXX` escapes.
+    pub fn string_serialized_len(&self) -> Option<usize> {
+        self.string().map(|s| serializer::Writer::new().string_len(s))
+    }
+
+    /// Reserialize the object into a writer, a piece at a time, without
+    /// building the output in memory. Errors from the writer are returned
+    /// unchanged.
     pub fn write_to<W: io::Write>(&self, mut w: W) -> io::Result<()> {
         serializer::serialize(self, &mut w)
     }
-
-    /// Serialize the object to byte array
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut ret = vec![];
-        self.write_to(&mut ret).unwrap();
-        ret
+
+    /// Reserialize the object into a writer with every array element and
+    /// object member on its own line, indented by `indent` spaces per level
+    /// of nesting. Empty arrays and objects are written as `[]` and `{}`.
+    pub fn write_pretty<W: io::Write>(&self, mut w: W, indent: usize) -> io::Result<()> {
+        let mut writer = serializer::Writer::new();
+        writer.max_width(Some(0));
+        writer.indent(indent);
+        writer.write(self, &mut w)
+    }
+
+    /// Reserialize the object compactly, as `to_string` does, but with
+    /// every non-ASCII character escaped so the output is plain ASCII; see
+    /// `serializer::Writer::escape_non_ascii`
+    pub fn to_string_ascii(&self) -> String {
+        let mut writer = serializer::Writer::new();
+        writer.escape_non_ascii(true);
+        let mut ret = String::new();
+        writer.write_to_fmt(self, &mut ret).expect("writing to a String cannot fail");
+        ret
+    }
+
+    /// Reserialize the object as a string laid out as by `write_pretty`
+    pub fn to_string_pretty(&self, indent: usize) -> String {
+        let mut ret = vec![];
+        self.write_pretty(&mut ret, indent).unwrap();
+        String::from_utf8(ret).expect("serializer writes UTF-8")
+    }
+
+    /// Reserialize the object into a `fmt::Write`, such as a `String`, as
+    /// `write_to` does into an `io::Write`
+    pub fn write_to_fmt<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        serializer::Writer::new().write_to_fmt(self, w)
+    }
+
+    /// Reserialize the object into a writer, letting `transform` substitute
+    /// nodes on the way out without changing the object itself; see
+    /// `serializer::Writer::write_with`
+    pub fn to_writer_with<W, F>(&self, w: &mut W, transform: F) -> io::Result<()>
+        where W: io::Write,
+              F: FnMut(&Json) -> Option<Json>
+    {
+        serializer::Writer::new().write_with(self, w, transform)
+    }
+
+    /// Serialize the object to byte array
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = vec![];
+        self.write_to(&mut ret).unwrap();
+        ret
+    }
+
+    /// Encodes the object in a compact binary format which loads faster
+    /// than Json text, for caching parsed documents. Numbers, strings and
+    /// member order are kept exactly. The format is private to this
+    /// version of the library, so do not use it for long-term storage.
+    pub fn to_cache_bytes(&self) -> Vec<u8> {
+        cache::encode(self)
+    }
+
+    /// Decodes an object written by `to_cache_bytes`. Malformed input is an
+    /// error, never a panic. Any depth of nesting which `to_cache_bytes`
+    /// writes is read back.
+    pub fn from_cache_bytes(data: &[u8]) -> Result<Json, Error> {
+        cache::decode(data)
+    }
+
+    /// Convert the Json object to something deserializable
+    pub fn into_deserialize<'a, T: serde::Deserialize<'a>>(self) -> Result<T, Error> {
+        de::Deserialize::deserialize(Deserializer::new(self))
+    }
+}
+
+/// Split an RFC 6901 Json Pointer into its unescaped tokens
+fn pointer_tokens(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(vec![]);
+    }
+    if !pointer.starts_with('/') {
+        return None;
+    }
+    Some(pointer[1..].split('/').map(|t| t.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+/// Parse a pointer token as an array index, which is decimal without
+/// leading zeros
+fn pointer_index(token: &str) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) ||
+       !token.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// The canonical form of a number, as used by `Json::coerce_numbers`, or
+/// None if it cannot be represented as a finite `f64`
+fn canonical_number(s: &str) -> Option<String> {
+    let x: f64 = s.parse().ok()?;
+    if !x.is_finite() {
+        return None;
+    }
+    let abs = x.abs();
+    if abs != 0.0 && !(1e-6..1e21).contains(&abs) {
+        Some(format!("{:e}", x))
+    } else {
+        Some(format!("{}", x))
+    }
+}
+
+/// The canonical form of a number as `canonical_number` gives it, but only
+/// if that has exactly the same value, so that nothing is rounded
+fn canonical_number_exact(s: &str) -> Option<String> {
+    let canon = canonical_number(s)?;
+    if decimal_value(s)? == decimal_value(&canon)? {
+        Some(canon)
+    } else {
+        None
+    }
+}
+
+/// The value of a number as its sign, its significant digits without
+/// leading or trailing zeros, and the power of ten by which `0.DIGITS` is
+/// multiplied, so that equal numbers compare equal however they are
+/// written. Zero has no digits.
+fn decimal_value(s: &str) -> Option<(bool, String, i64)> {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (mantissa, exp) = match s.find(['e', 'E']) {
+        Some(n) => (&s[..n], &s[n + 1..]),
+        None => (s, "0"),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", int, frac);
+    let significant = digits.trim_start_matches('0');
+    if significant.is_empty() {
+        return Some((neg, String::new(), 0));
+    }
+    let leading = digits.len() - significant.len();
+    let point = exp.parse::<i64>().ok()?.checked_add(int.len() as i64 - leading as i64)?;
+    Some((neg, significant.trim_end_matches('0').to_owned(), point))
+}
+
+/// The digits of a number with its fraction and exponent applied, such as
+/// `-1500` for `-1.5e3`, or None if the value has a non-zero fraction or
+/// more than `max_digits` digits
+fn integer_digits(s: &str, max_digits: usize) -> Option<String> {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (mantissa, exp) = match s.find(['e', 'E']) {
+        Some(n) => (&s[..n], &s[n + 1..]),
+        None => (s, "0"),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", int, frac);
+    let significant = digits.trim_start_matches('0');
+    if significant.is_empty() {
+        return Some("0".to_owned());
+    }
+    // Position of the decimal point within `significant`
+    let point = exp.parse::<i64>().ok()?
+        .checked_add(significant.len() as i64 - frac.len() as i64)?;
+    if point <= 0 || point as u64 > max_digits as u64 {
+        return None;
+    }
+    let point = point as usize;
+    if significant.len() > point && significant[point..].bytes().any(|b| b != b'0') {
+        return None;
+    }
+    let mut ret = String::with_capacity(point + 1);
+    if neg {
+        ret.push('-');
     }
+    ret.push_str(&significant[..point.min(significant.len())]);
+    ret.extend((significant.len()..point).map(|_| '0'));
+    Some(ret)
+}
 
-    /// Convert the Json object to something deserializable
-    pub fn into_deserialize<'a, T: serde::Deserialize<'a>>(self) -> Result<T, Error> {
-        de::Deserialize::deserialize(Deserializer::new(self))
+/// Quote and escape a string as a Json string literal, as the serializer
+/// would for a string value. Useful when building Json text by hand.
+pub fn to_json_string_literal(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    escape_into(s, &mut ret);
+    ret
+}
+
+/// Append the quoted and escaped form of a string, as produced by
+/// `to_json_string_literal`, to an existing buffer
+pub fn escape_into(s: &str, out: &mut String) {
+    serializer::Writer::new().write_string_fmt(s, out).expect("writing to a String cannot fail");
+}
+
+/// Append the quoted and escaped form of a string, as produced by
+/// `to_json_string_literal`, to an existing byte buffer
+pub fn escape_into_vec(s: &str, out: &mut Vec<u8>) {
+    serializer::Writer::new().write_string(s, out).expect("writing to a Vec cannot fail");
+}
+
+/// The default Json object is null
+impl Default for Json {
+    fn default() -> Json {
+        Json(JsonInner::Null)
     }
 }
 
+/// Parse arbitrary bytes as a Json object
+///
+/// This never panics, whatever the input, so it is the entry point used
+/// for fuzzing. Nesting depth is limited to `parser::DEFAULT_MAX_DEPTH`.
+pub fn parse_bytes(data: &[u8]) -> Result<Json, Error> {
+    Json::from_slice(data)
+}
+
+/// Parse a string as a Json object; the same as `Json::from_str`
+pub fn from_str(s: &str) -> Result<Json, Error> {
+    Json::from_str(s)
+}
+
+/// Parse a byte slice as a Json object; the same as `Json::from_slice`
+/// and `parse_bytes`
+pub fn from_slice(data: &[u8]) -> Result<Json, Error> {
+    Json::from_slice(data)
+}
+
+/// Parse the contents of a reader as a Json object; the same as
+/// `Json::from_reader`
+pub fn from_reader<R: io::Read>(r: R) -> Result<Json, Error> {
+    Json::from_reader(r)
+}
+
+/// Parse the one value starting at byte `start` of `data`, after any
+/// whitespace, ignoring whatever text surrounds it, e.g. to pull a payload
+/// out of a log line. Returns the value and the offset in `data` of the
+/// byte just after it. The positions in parse errors count from `start`.
+pub fn parse_at(data: &[u8], start: usize) -> Result<(Json, usize), Error> {
+    let rest = match data.get(start..) {
+        Some(rest) => rest,
+        None => return Err(Error(ErrorInner::Other(format!("start offset {} is past the end of the input", start)))),
+    };
+    let mut parser = parser::Parser::new(rest.iter().map(|x| Ok(*x)));
+    let json = parser.parse_one()?;
+    Ok((json, start + parser.byte_offset()))
+}
+
+/// Check that the input is a stream of whitespace-separated Json values,
+/// such as a newline-delimited Json file, returning an error, with its
+/// position, for each malformed value. See `Parser::validate_stream`.
+pub fn validate_stream<R: io::Read>(r: R) -> Vec<Error> {
+    parser::Parser::new(io::Read::bytes(io::BufReader::new(r))).validate_stream()
+}
+
 impl From<()> for Json {
     fn from(_: ()) -> Json {
         Json(JsonInner::Null)
@@ -278,14 +1551,30 @@ impl From<Vec<(String, Json)>> for Json {
     }
 }
 
+/// Serializes the Json object to compact JSON text. Note that this is not
+/// the same as `Json::string()`: a string node is converted to a quoted and
+/// escaped literal, not unwrapped.
+impl From<Json> for String {
+    fn from(json: Json) -> String {
+        json.to_string()
+    }
+}
+
+/// Serializes the Json object to compact JSON text, as `From<Json>` does.
+impl<'a> From<&'a Json> for String {
+    fn from(json: &'a Json) -> String {
+        json.to_string()
+    }
+}
+
 impl fmt::Display for Json {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut v = vec![];
-        serializer::serialize(self, &mut v).unwrap();
-        f.write_str(unsafe { std::str::from_utf8_unchecked(&v) })
+        self.write_to_fmt(f)
     }
 }
 
+/// Looks up a member of an object, as `Json::get` does, but panics if this
+/// is not an object or has no such member
 impl<'a> ops::Index<&'a str> for Json {
     type Output = Json;
     #[inline]
@@ -303,6 +1592,9 @@ impl<'a> ops::Index<&'a str> for Json {
     }
 }
 
+/// Looks up an element of an array, as `Json::get_index` does, but panics
+/// if this is not an array or the index is out of range, like indexing a
+/// `Vec`
 impl ops::Index<usize> for Json {
     type Output = Json;
     #[inline]
@@ -464,6 +1756,1110 @@ mod tests {
         assert!(mt_obj.is_empty());
     }
 
+    #[test]
+    fn into_inner() {
+        let s = Json::from_str("\"val\"").unwrap();
+        let arr = Json::from_str("[1, \"two\"]").unwrap();
+        let obj = Json::from_str("{\"k\": null}").unwrap();
+
+        assert_eq!(s.clone().into_string(), Ok("val".to_owned()));
+        assert_eq!(arr.clone().into_vec(), Ok(vec![Json::from(1u8), Json::from("two")]));
+        assert_eq!(obj.clone().into_object(), Ok(vec![("k".to_owned(), Json::from(()))]));
+
+        assert_eq!(arr.clone().into_string(), Err(arr.clone()));
+        assert_eq!(obj.clone().into_string(), Err(obj.clone()));
+        assert_eq!(s.clone().into_vec(), Err(s.clone()));
+        assert_eq!(obj.clone().into_vec(), Err(obj.clone()));
+        assert_eq!(s.clone().into_object(), Err(s.clone()));
+        assert_eq!(arr.clone().into_object(), Err(arr));
+        let num = Json::from(5u8);
+        assert_eq!(num.clone().into_string(), Err(num));
+    }
+
+    #[test]
+    fn nested_get() {
+        let obj = Json::from_str("{\"a\": {\"b\": {\"c\": 1}, \"d\": 2}, \"e\": [3]}").unwrap();
+        assert_eq!(obj.get2("a", "d").and_then(Json::num), Some("2"));
+        assert_eq!(obj.get2("a", "b"), Some(&obj["a"]["b"]));
+        assert_eq!(obj.get3("a", "b", "c").and_then(Json::num), Some("1"));
+
+        assert!(obj.get2("x", "b").is_none());
+        assert!(obj.get2("a", "x").is_none());
+        assert!(obj.get3("x", "b", "c").is_none());
+        assert!(obj.get3("a", "x", "c").is_none());
+        assert!(obj.get3("a", "d", "c").is_none());
+        assert!(obj.get2("e", "0").is_none());
+    }
+
+    #[test]
+    fn free_functions() {
+        let input = "{\"a\": [1, true, null], \"b\": \"x\"}";
+        let expected = Json::from_str(input).unwrap();
+        assert_eq!(::from_str(input).unwrap(), expected);
+        assert_eq!(::from_slice(input.as_bytes()).unwrap(), expected);
+        assert_eq!(Json::from_slice(input.as_bytes()).unwrap(), expected);
+        assert_eq!(Json::from_slice(b"\"caf\xc3\xa9\"").unwrap(), Json::from("caf\u{e9}"));
+        assert!(Json::from_slice(b"\"caf\xe9\"").is_err());
+        assert_eq!(::from_reader(input.as_bytes()).unwrap(), expected);
+        assert_eq!(::from_reader(input.as_bytes()).unwrap(), Json::from_reader(input.as_bytes()).unwrap());
+
+        assert!(::from_str("[1,").is_err());
+        assert!(::from_slice(b"[1,").is_err());
+        assert!(Json::from_slice(b"").is_err());
+        assert!(::from_reader(&b"[1,"[..]).is_err());
+    }
+
+    #[test]
+    fn from_reader_buffers() {
+        use std::io;
+        use {Error, ErrorInner};
+
+        // Counts calls to `read`, which are system calls for files
+        struct Counting<'a> {
+            data: &'a [u8],
+            reads: usize,
+        }
+
+        impl<'a> io::Read for Counting<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                self.data.read(buf)
+            }
+        }
+
+        let json = Json::from((0..1000u32).map(Json::from).collect::<Vec<_>>());
+        let text = json.to_bytes();
+        assert!(text.len() > 4000);
+        let mut reader = Counting { data: &text, reads: 0 };
+        assert_eq!(Json::from_reader(&mut reader).unwrap(), json);
+        assert!(reader.reads < 10, "{} reads", reader.reads);
+
+        // Read errors are still reported
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk on fire"))
+            }
+        }
+        match Json::from_reader(Failing) {
+            Err(Error(ErrorInner::Parser(e))) => assert!(e.to_string().ends_with("disk on fire"), "{}", e),
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn parse_at() {
+        let line = b"2024-01-01 INFO done result={\"ok\": true, \"ids\": [1, 2]} elapsed=15ms";
+        let start = line.windows(7).position(|w| w == b"result=").unwrap() + 7;
+        let (json, end) = ::parse_at(line, start).unwrap();
+        assert_eq!(json, Json::from_str("{\"ok\": true, \"ids\": [1, 2]}").unwrap());
+        assert_eq!(&line[end..], b" elapsed=15ms");
+
+        // Leading whitespace is skipped, and a number ends where it ends
+        let (json, end) = ::parse_at(b"x =  -1.5e3, y", 3).unwrap();
+        assert_eq!(json, Json::from_str("-1.5e3").unwrap());
+        assert_eq!(end, 11);
+        let (json, end) = ::parse_at(b"n=42", 2).unwrap();
+        assert_eq!(json, Json::from(42));
+        assert_eq!(end, 4);
+
+        assert!(::parse_at(b"a={\"unterminated\": 1", 2).is_err());
+        assert!(::parse_at(b"abc", 1).is_err());
+        assert!(::parse_at(b"abc", 3).is_err());
+        let err = ::parse_at(b"abc", 4).unwrap_err();
+        assert_eq!(err.to_string(), "start offset 4 is past the end of the input");
+    }
+
+    #[test]
+    fn walk_mut() {
+        use PathSegment;
+
+        let mut obj = Json::from_str("{\"user\": {\"name\": \"a\", \"password\": \"hunter2\"}, \
+                                       \"admins\": [{\"password\": \"x\"}, {\"password\": \"y\"}], \
+                                       \"password\": \"top\"}").unwrap();
+        let mut visited = 0;
+        obj.walk_mut(|path, node| {
+            visited += 1;
+            // Redact only `user.password` and `admins[*].password`
+            let redact = match path {
+                [PathSegment::Key(a), PathSegment::Key(b)] => a == "user" && b == "password",
+                [PathSegment::Key(a), PathSegment::Index(_), PathSegment::Key(b)] => a == "admins" && b == "password",
+                _ => false,
+            };
+            if redact {
+                *node = Json::from("***");
+            }
+        });
+        assert_eq!(visited, 10);
+        assert_eq!(obj.to_string(), "{\"user\": {\"name\": \"a\", \"password\": \"***\"}, \
+                                     \"admins\": [{\"password\": \"***\"}, {\"password\": \"***\"}], \
+                                     \"password\": \"top\"}");
+
+        // Replacement children are visited
+        let mut arr = Json::from_str("[1]").unwrap();
+        let mut paths = vec![];
+        arr.walk_mut(|path, node| {
+            paths.push(path.to_vec());
+            if path.is_empty() {
+                *node = Json::from_str("{\"k\": [2]}").unwrap();
+            }
+        });
+        assert_eq!(paths, vec![
+            vec![],
+            vec![PathSegment::Key("k".to_owned())],
+            vec![PathSegment::Key("k".to_owned()), PathSegment::Index(0)],
+        ]);
+    }
+
+    #[test]
+    fn rename_keys() {
+        fn snake_case(key: &str) -> Option<String> {
+            if !key.chars().any(|c| c.is_ascii_uppercase()) {
+                return None;
+            }
+            let mut ret = String::new();
+            for c in key.chars() {
+                if c.is_ascii_uppercase() {
+                    ret.push('_');
+                    ret.push(c.to_ascii_lowercase());
+                } else {
+                    ret.push(c);
+                }
+            }
+            Some(ret)
+        }
+
+        let mut obj = Json::from_str("{\"userId\": 1, \"tags\": [{\"tagName\": \"camelCase\"}], \"homeAddress\": {\"zipCode\": \"x\", \"city\": \"y\"}}").unwrap();
+        obj.rename_keys(snake_case);
+        assert_eq!(obj.to_string(), "{\"user_id\": 1, \"tags\": [{\"tag_name\": \"camelCase\"}], \"home_address\": {\"zip_code\": \"x\", \"city\": \"y\"}}");
+
+        // Collisions keep both members
+        let mut obj = Json::from_str("{\"a_b\": 1, \"aB\": 2}").unwrap();
+        let mut count = 0;
+        obj.rename_keys(|key| { count += 1; snake_case(key) });
+        assert_eq!(count, 2);
+        assert_eq!(obj.to_string(), "{\"a_b\": 1, \"a_b\": 2}");
+    }
+
+    #[test]
+    fn to_string_pretty() {
+        let json = Json::from_str("{\"a\": [1, {\"b\": null}], \"c\": {}, \"d\": [], \"e\\n\": \"x\\u0001\", \"f\": 1.50E+2}").unwrap();
+        assert_eq!(json.to_string_pretty(4), "{
+    \"a\": [
+        1,
+        {
+            \"b\": null
+        }
+    ],
+    \"c\": {},
+    \"d\": [],
+    \"e\\n\": \"x\\u0001\",
+    \"f\": 1.50E+2
+}");
+        assert_eq!(json.to_string_pretty(1), "{\n \"a\": [\n  1,\n  {\n   \"b\": null\n  }\n ],\n \"c\": {},\n \"d\": [],\n \"e\\n\": \"x\\u0001\",\n \"f\": 1.50E+2\n}");
+        assert_eq!(Json::from_str(&json.to_string_pretty(2)).unwrap(), json);
+
+        // Aside from whitespace, the output is that of the compact serializer
+        let strip = |s: &str| s.replace(['\n', ' '], "");
+        assert_eq!(strip(&json.to_string_pretty(3)), strip(&json.to_string()));
+
+        // With no indentation, only the line breaks remain
+        assert_eq!(Json::from_str("[1, [2]]").unwrap().to_string_pretty(0), "[\n1,\n[\n2\n]\n]");
+
+        // Scalars and empty containers are written on one line
+        for s in &["[]", "{}", "\"a b\"", "-0.5", "true"] {
+            assert_eq!(Json::from_str(s).unwrap().to_string_pretty(2), *s);
+        }
+
+        let mut out = vec![];
+        json.write_pretty(&mut out, 4).unwrap();
+        assert_eq!(out, json.to_string_pretty(4).into_bytes());
+    }
+
+    #[test]
+    fn prune_empty() {
+        let mut obj = Json::from_str("{\"a\": 1, \"b\": {}, \"c\": [], \"d\": [0, [], {}, \"\"], \"e\": {\"f\": null}}").unwrap();
+        obj.prune_empty();
+        assert_eq!(obj.to_string(), "{\"a\": 1, \"d\": [0, \"\"], \"e\": {\"f\": null}}");
+
+        // An object emptied by pruning is itself removed
+        let mut obj = Json::from_str("{\"a\": {\"b\": {}}, \"c\": true}").unwrap();
+        obj.prune_empty();
+        assert_eq!(obj.to_string(), "{\"c\": true}");
+
+        // Emptiness cascades all the way up, leaving the root in place
+        let mut obj = Json::from_str("{\"a\": [{\"b\": [[], {}]}, []], \"c\": {\"d\": {\"e\": []}}}").unwrap();
+        obj.prune_empty();
+        assert_eq!(obj, Json::from_str("{}").unwrap());
+        let mut arr = Json::from_str("[[[]]]").unwrap();
+        arr.prune_empty();
+        assert_eq!(arr, Json::from_str("[]").unwrap());
+
+        let mut num = Json::from(3u8);
+        num.prune_empty();
+        assert_eq!(num, Json::from(3u8));
+    }
+
+    #[test]
+    fn drop_null_fields() {
+        let mut obj = Json::from_str("{\"a\": null, \"b\": {\"c\": null, \"d\": 0}, \"e\": [null, {\"f\": null}, [null]], \"g\": {\"h\": null}}").unwrap();
+        obj.drop_null_fields();
+        assert_eq!(obj.to_string(), "{\"b\": {\"d\": 0}, \"e\": [null, {}, [null]], \"g\": {}}");
+
+        // Non-null values, including falsy ones, are untouched
+        let orig = Json::from_str("{\"a\": false, \"b\": 0, \"c\": \"\", \"d\": [], \"e\": {}}").unwrap();
+        let mut obj = orig.clone();
+        obj.drop_null_fields();
+        assert_eq!(obj, orig);
+
+        // A null at the root is not a field
+        let mut null = Json::from(());
+        null.drop_null_fields();
+        assert_eq!(null, Json::from(()));
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-normalization")]
+    fn normalize_nfc() {
+        let mut obj = Json::empty_object()
+            .with("caf\u{65}\u{301}", Json::empty_array().with_pushed("\u{65}\u{301}t\u{65}\u{301}"))
+            .with("plain", "ascii only")
+            .with("n", 1.5);
+        obj.normalize_nfc();
+        let expected = Json::empty_object()
+            .with("caf\u{e9}", Json::empty_array().with_pushed("\u{e9}t\u{e9}"))
+            .with("plain", "ascii only")
+            .with("n", 1.5);
+        assert_eq!(obj, expected);
+        assert_eq!(obj.get("caf\u{e9}").and_then(|v| v[0].string()).map(str::len), Some(5));
+    }
+
+    #[test]
+    fn truncate_strings() {
+        let long_key = "a very long key indeed";
+        let mut obj = Json::empty_object()
+            .with(long_key, "0123456789")
+            .with("short", "0123")
+            .with("nested", Json::empty_array().with_pushed("abcdef").with_pushed(12345678u32))
+            .with("multi", "h\u{e9}\u{e9}\u{1f600}llo");
+        obj.truncate_strings(4);
+        let expected = Json::empty_object()
+            .with(long_key, "0123\u{2026}")
+            .with("short", "0123")
+            .with("nested", Json::empty_array().with_pushed("abcd\u{2026}").with_pushed(12345678u32))
+            .with("multi", "h\u{e9}\u{e9}\u{1f600}\u{2026}");
+        assert_eq!(obj, expected);
+
+        let mut s = Json::from("abc");
+        s.truncate_strings(0);
+        assert_eq!(s, Json::from("\u{2026}"));
+        let mut s = Json::from("");
+        s.truncate_strings(0);
+        assert_eq!(s, Json::from(""));
+    }
+
+    #[test]
+    fn numeric_conversions() {
+        let num = |s: &str| Json::from_str(s).unwrap();
+
+        assert_eq!(num("1.5").as_f64(), Some(1.5));
+        assert_eq!(num("-2e-3").as_f64(), Some(-0.002));
+        assert_eq!(num("1e308").as_f64(), Some(1e308));
+        assert_eq!(num("1e400").as_f64(), None);
+        assert_eq!(num("-1e400").as_f64(), None);
+        assert_eq!(num("1e-400").as_f64(), Some(0.0));
+
+        for &(s, i, u) in &[
+            ("0", Some(0), Some(0)),
+            ("-0", Some(0), Some(0)),
+            ("42", Some(42), Some(42)),
+            ("-42", Some(-42), None),
+            ("1.0", Some(1), Some(1)),
+            ("1.5e3", Some(1500), Some(1500)),
+            ("-2.50E+1", Some(-25), None),
+            ("12000e-3", Some(12), Some(12)),
+            ("0.000e999999999999", Some(0), Some(0)),
+            ("1.5", None, None),
+            ("1e-3", None, None),
+            ("-0.5", None, None),
+            ("9223372036854775807", Some(i64::MAX), Some(i64::MAX as u64)),
+            ("-9223372036854775808", Some(i64::MIN), None),
+            ("9223372036854775808", None, Some(1 << 63)),
+            ("18446744073709551615", None, Some(u64::MAX)),
+            ("18446744073709551616", None, None),
+            ("1.8446744073709551615e19", None, Some(u64::MAX)),
+            ("1e19", None, Some(10_000_000_000_000_000_000)),
+            ("1e20", None, None),
+            ("1e99999999999999999999", None, None),
+        ] {
+            assert_eq!(num(s).as_i64(), i, "{}", s);
+            assert_eq!(num(s).as_u64(), u, "{}", s);
+        }
+
+        // Only numbers convert, not numeric strings
+        for json in &[Json::from("1"), Json::from(true), Json::from(()), num("[1]")] {
+            assert_eq!(json.as_f64(), None);
+            assert_eq!(json.as_i64(), None);
+            assert_eq!(json.as_u64(), None);
+        }
+    }
+
+    #[test]
+    fn as_accessors() {
+        let json = Json::from_str("{\"b\": false, \"s\": \"x\", \"a\": [1, 2], \"o\": {\"k\": null}, \"n\": -1.50e2}").unwrap();
+        assert_eq!(json["b"].as_bool(), Some(false));
+        assert_eq!(json["s"].as_str(), Some("x"));
+        assert_eq!(json["a"].as_array(), Some(&[Json::from(1u8), Json::from(2u8)][..]));
+        assert_eq!(json["o"].as_object(), Some(&[("k".to_owned(), Json::from(()))][..]));
+        assert_eq!(json["n"].as_number_str(), Some("-1.50e2"));
+        assert_eq!(json.as_object().map(|o| o.len()), Some(5));
+
+        // Each gives None for every other type
+        for (key, val) in json.as_object().unwrap() {
+            assert_eq!(val.as_bool().is_some(), key == "b");
+            assert_eq!(val.as_str().is_some(), key == "s");
+            assert_eq!(val.as_array().is_some(), key == "a");
+            assert_eq!(val.as_object().is_some(), key == "o");
+            assert_eq!(val.as_number_str().is_some(), key == "n");
+        }
+        assert_eq!(json["o"]["k"].as_bool(), None);
+        // A string of digits is not a number
+        assert_eq!(Json::from("12").as_number_str(), None);
+    }
+
+    #[test]
+    fn as_str_or() {
+        let s = Json::from("val");
+        assert_eq!(s.as_str_or("default"), "val");
+        assert_eq!(Json::from("").as_str_or("default"), "");
+        assert_eq!(Json::from(5).as_str_or("default"), "default");
+        assert_eq!(Json::from(()).as_str_or("default"), "default");
+
+        // Converting to a String either unwraps or serializes
+        assert_eq!(s.clone().into_string(), Ok("val".to_owned()));
+        assert_eq!(String::from(s), "\"val\"");
+    }
+
+    #[test]
+    fn pointer() {
+        let obj = Json::from_str("{\"a\": [10, {\"b\": true}], \"c/d\": 1, \"e~f\": 2, \"\": 3}").unwrap();
+        assert_eq!(obj.pointer(""), Some(&obj));
+        assert_eq!(obj.pointer("/a/0"), Some(&Json::from(10)));
+        assert_eq!(obj.pointer("/a/1/b"), Some(&Json::from(true)));
+        assert_eq!(obj.pointer("/c~1d"), Some(&Json::from(1)));
+        assert_eq!(obj.pointer("/e~0f"), Some(&Json::from(2)));
+        assert_eq!(obj.pointer("/"), Some(&Json::from(3)));
+
+        assert_eq!(obj.pointer("a"), None);
+        assert_eq!(obj.pointer("/a/2"), None);
+        assert_eq!(obj.pointer("/a/01"), None);
+        assert_eq!(obj.pointer("/a/-"), None);
+        assert_eq!(obj.pointer("/a/0/x"), None);
+        assert_eq!(obj.pointer("/x"), None);
+    }
+
+    #[test]
+    fn replace_pointer() {
+        let mut obj = Json::from_str("{\"a\": [10, {\"b\": true}], \"c\": 1, \"c\": 2}").unwrap();
+        let old = obj.replace_pointer("/a/1/b", Json::from("new")).unwrap();
+        assert_eq!(old, Json::from(true));
+        assert_eq!(obj.to_string(), "{\"a\": [10, {\"b\": \"new\"}], \"c\": 1, \"c\": 2}");
+
+        // With duplicate keys the first is replaced, as `pointer` finds it
+        assert_eq!(obj.replace_pointer("/c", Json::from(3)).unwrap(), Json::from(1));
+        assert_eq!(obj.pointer("/c"), Some(&Json::from(3)));
+
+        // Missing paths are errors and change nothing
+        let before = obj.clone();
+        for ptr in &["/x", "/a/2", "/a/-", "/a/0/b", "a"] {
+            let err = obj.replace_pointer(ptr, Json::from(())).unwrap_err();
+            assert_eq!(err.to_string(), format!("pointer `{}` does not resolve", ptr));
+        }
+        assert_eq!(obj, before);
+
+        let old = obj.replace_pointer("", Json::from(())).unwrap();
+        assert_eq!(old, before);
+        assert_eq!(obj, Json::from(()));
+
+        let mut obj = old;
+        *obj.pointer_mut("/a/0").unwrap() = Json::from(11);
+        assert_eq!(obj.pointer("/a/0"), Some(&Json::from(11)));
+        assert!(obj.pointer_mut("/a/9").is_none());
+    }
+
+    #[test]
+    fn merge_arrays_by_key() {
+        let a = Json::from_str("[{\"id\": 1, \"name\": \"a\", \"meta\": {\"x\": 1, \"tags\": [1]}}, {\"id\": 2, \"name\": \"b\"}, {\"name\": \"no id\"}]").unwrap();
+        let b = Json::from_str("[{\"id\": 2, \"age\": 5}, {\"id\": 3, \"name\": \"c\"}, {\"id\": 1, \"meta\": {\"y\": 2, \"tags\": [2]}}, {\"name\": \"no id\"}]").unwrap();
+        let merged = Json::merge_arrays_by_key(&a, &b, "id").unwrap();
+        assert_eq!(merged.to_string(), "[{\"id\": 1, \"name\": \"a\", \"meta\": {\"x\": 1, \"tags\": [2], \"y\": 2}}, \
+                                         {\"id\": 2, \"name\": \"b\", \"age\": 5}, \
+                                         {\"name\": \"no id\"}, \
+                                         {\"id\": 3, \"name\": \"c\"}, \
+                                         {\"name\": \"no id\"}]");
+
+        // Disjoint records are all carried through
+        let c = Json::from_str("[{\"id\": \"1\"}, {\"id\": 7}]").unwrap();
+        let merged = Json::merge_arrays_by_key(&a, &c, "id").unwrap();
+        assert_eq!(merged.array().unwrap().len(), 5);
+        assert_eq!(&merged[..3], a.array().unwrap());
+        assert_eq!(&merged[3..], c.array().unwrap());
+
+        let empty = Json::from_str("[]").unwrap();
+        assert_eq!(Json::merge_arrays_by_key(&empty, &empty, "id").unwrap(), empty);
+
+        // Errors
+        let obj = Json::from_str("{\"id\": 1}").unwrap();
+        let mixed = Json::from_str("[{\"id\": 1}, 5]").unwrap();
+        assert!(Json::merge_arrays_by_key(&obj, &a, "id").is_err());
+        assert!(Json::merge_arrays_by_key(&a, &obj, "id").is_err());
+        assert!(Json::merge_arrays_by_key(&a, &mixed, "id").is_err());
+        assert_eq!(Json::merge_arrays_by_key(&mixed, &a, "id").unwrap_err().to_string(),
+                   "expected an array of objects, found a number");
+    }
+
+    #[test]
+    fn merge_defaults() {
+        let defaults = Json::from_str("{\"host\": \"localhost\", \"port\": 80, \"tls\": {\"enabled\": false, \"ciphers\": [\"a\", \"b\"]}, \"tags\": [1, 2]}").unwrap();
+        let mut config = Json::from_str("{\"port\": 8080, \"tls\": {\"enabled\": true}, \"tags\": [3], \"user\": null}").unwrap();
+        config.merge_defaults(&defaults);
+        assert_eq!(config.to_string(), "{\"port\": 8080, \"tls\": {\"enabled\": true, \"ciphers\": [\"a\", \"b\"]}, \"tags\": [3], \"user\": null, \"host\": \"localhost\"}");
+
+        // Present values win even when the default is an object
+        let mut config = Json::from_str("{\"tls\": null}").unwrap();
+        config.merge_defaults(&defaults);
+        assert_eq!(config.get("tls"), Some(&Json::from(())));
+
+        let mut empty = Json::from_str("{}").unwrap();
+        empty.merge_defaults(&defaults);
+        assert_eq!(empty, defaults);
+
+        let mut arr = Json::from_str("[{}]").unwrap();
+        arr.merge_defaults(&Json::from_str("[{\"a\": 1}, 2]").unwrap());
+        assert_eq!(arr.to_string(), "[{}]");
+    }
+
+    #[test]
+    fn project() {
+        let obj = Json::from_str("{\"a\": {\"b\": 1, \"c\": [10, 20, 30, {\"d\": true, \"e\": false}]}, \"f\": \"x\", \"g\": null}").unwrap();
+
+        let proj = obj.project(&["/a/b", "/a/c/3/e"]);
+        assert_eq!(proj.to_string(), "{\"a\": {\"b\": 1, \"c\": [null, null, null, {\"e\": false}]}}");
+        assert_eq!(proj.pointer("/a/b"), obj.pointer("/a/b"));
+        assert_eq!(proj.pointer("/a/c/3/e"), obj.pointer("/a/c/3/e"));
+
+        // Whole subtrees, overlapping pointers and the original order
+        let proj = obj.project(&["/g", "/a/c/1", "/a/c", "/missing", "/f/0", "bad"]);
+        assert_eq!(proj.to_string(), "{\"a\": {\"c\": [10, 20, 30, {\"d\": true, \"e\": false}]}, \"g\": null}");
+
+        assert_eq!(obj.project(&[""]), obj);
+        assert_eq!(obj.project(&[]).to_string(), "{}");
+        assert_eq!(Json::from(5).project(&["/a"]), Json::from(()));
+    }
+
+    #[test]
+    fn paths_to_key() {
+        let obj = Json::from_str("{\"id\": 1, \"items\": [{\"id\": 2, \"sub\": {\"id\": 3}}, {\"name\": \"x\"}, {\"id\": {\"id\": 4}}], \"a/b\": {\"id\": 5}}").unwrap();
+        let paths = obj.paths_to_key("id");
+        assert_eq!(paths, vec![
+            "/id",
+            "/items/0/id",
+            "/items/0/sub/id",
+            "/items/2/id",
+            "/items/2/id/id",
+            "/a~1b/id",
+        ]);
+        let found: Vec<_> = paths.iter().map(|p| obj.pointer(p).unwrap().to_string()).collect();
+        assert_eq!(found, vec!["1", "2", "3", "{\"id\": 4}", "4", "5"]);
+
+        assert!(obj.paths_to_key("missing").is_empty());
+        assert_eq!(obj.paths_to_key("a/b"), vec!["/a~1b"]);
+    }
+
+    #[test]
+    fn kind() {
+        use JsonType;
+
+        let cases = [
+            ("null", JsonType::Null, true),
+            ("false", JsonType::Bool, true),
+            ("-1.5", JsonType::Number, true),
+            ("\"[]\"", JsonType::String, true),
+            ("[]", JsonType::Array, false),
+            ("[1]", JsonType::Array, false),
+            ("{}", JsonType::Object, false),
+            ("{\"a\": 1}", JsonType::Object, false),
+        ];
+        for &(s, kind, scalar) in &cases {
+            let json = Json::from_str(s).unwrap();
+            assert_eq!(json.kind(), kind, "{}", s);
+            assert_eq!(json.is_scalar(), scalar, "{}", s);
+            assert_eq!(json.is_container(), !scalar, "{}", s);
+
+            let predicates = [json.is_null(), json.is_bool(), json.is_number(),
+                              json.is_string(), json.is_array(), json.is_object()];
+            assert_eq!(predicates.iter().filter(|&&p| p).count(), 1, "{}", s);
+            assert!(predicates[kind as usize], "{}", s);
+        }
+
+        // A numeric-looking string is a string
+        let s = Json::from("12");
+        assert!(s.is_string());
+        assert!(!s.is_number());
+    }
+
+    #[test]
+    fn number_is_integer() {
+        for &(input, integer) in &[("5", true), ("-0", true), ("5.0", false), ("5e2", false), ("-5E-2", false)] {
+            let num = Json::from_str(input).unwrap();
+            assert_eq!(num.number_is_integer(), integer, "{}", input);
+            assert_eq!(num.number_is_float(), !integer, "{}", input);
+        }
+        for non_num in &[Json::from("5"), Json::from(true), Json::from(())] {
+            assert!(!non_num.number_is_integer());
+            assert!(!non_num.number_is_float());
+        }
+    }
+
+    #[test]
+    fn wide_integers() {
+        let big = Json::from_str("18446744073709551616").unwrap();
+        assert_eq!(big.as_u128(), Some(u64::MAX as u128 + 1));
+        assert_eq!(big.as_i128(), Some(u64::MAX as i128 + 1));
+
+        let neg = Json::from_str("-9223372036854775809").unwrap();
+        assert_eq!(neg.as_i128(), Some(i64::MIN as i128 - 1));
+        assert_eq!(neg.as_u128(), None);
+
+        let max = Json::from_str("340282366920938463463374607431768211455").unwrap();
+        assert_eq!(max.as_u128(), Some(u128::MAX));
+        assert_eq!(max.as_i128(), None);
+        assert_eq!(Json::from_str("340282366920938463463374607431768211456").unwrap().as_u128(), None);
+        assert_eq!(Json::from_str("-170141183460469231731687303715884105729").unwrap().as_i128(), None);
+
+        assert_eq!(Json::from_str("0").unwrap().as_u128(), Some(0));
+        assert_eq!(Json::from_str("1.0").unwrap().as_i128(), None);
+        assert_eq!(Json::from_str("1e3").unwrap().as_u128(), None);
+        assert_eq!(Json::from("5").as_i128(), None);
+    }
+
+    #[test]
+    fn as_byte_vec() {
+        assert_eq!(Json::from_str("[0, 1, 127, 255]").unwrap().as_byte_vec(), Some(vec![0, 1, 127, 255]));
+        assert_eq!(Json::from_str("[]").unwrap().as_byte_vec(), Some(vec![]));
+
+        assert_eq!(Json::from_str("[1, 256]").unwrap().as_byte_vec(), None);
+        assert_eq!(Json::from_str("[-1]").unwrap().as_byte_vec(), None);
+        assert_eq!(Json::from_str("[1.0]").unwrap().as_byte_vec(), None);
+        assert_eq!(Json::from_str("[\"1\"]").unwrap().as_byte_vec(), None);
+        assert_eq!(Json::from_str("\"\\u0001\"").unwrap().as_byte_vec(), None);
+        assert_eq!(Json::from(1).as_byte_vec(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "base64")]
+    fn as_base64_bytes() {
+        assert_eq!(Json::from("aGVsbG8=").as_base64_bytes(), Some(b"hello".to_vec()));
+        assert_eq!(Json::from("AP8+/w==").as_base64_bytes(), Some(vec![0, 255, 62, 255]));
+        assert_eq!(Json::from("").as_base64_bytes(), Some(vec![]));
+
+        assert_eq!(Json::from("aGVsbG8").as_base64_bytes(), None);
+        assert_eq!(Json::from("not base64!").as_base64_bytes(), None);
+        assert_eq!(Json::from("_-8=").as_base64_bytes(), None);
+        assert_eq!(Json::from_str("[104, 105]").unwrap().as_base64_bytes(), None);
+        assert_eq!(Json::from(5).as_base64_bytes(), None);
+    }
+
+    #[test]
+    fn try_fold() {
+        fn add(sum: f64, elem: &Json) -> Result<f64, String> {
+            match elem.num() {
+                Some(n) => Ok(sum + n.parse::<f64>().unwrap()),
+                None => Err(format!("not a number: {}", elem)),
+            }
+        }
+
+        let arr = Json::from_str("[1, 2, 3.5]").unwrap();
+        assert_eq!(arr.try_fold(0.0, add), Some(Ok(6.5)));
+        assert_eq!(Json::from_str("[]").unwrap().try_fold(1.0, add), Some(Ok(1.0)));
+
+        // Stops at the first bad element
+        let arr = Json::from_str("[1, true, \"x\"]").unwrap();
+        let mut seen = 0;
+        let res = arr.try_fold(0.0, |sum, elem| { seen += 1; add(sum, elem) });
+        assert_eq!(res, Some(Err("not a number: true".to_owned())));
+        assert_eq!(seen, 2);
+
+        assert_eq!(Json::from_str("{\"a\": 1}").unwrap().try_fold(0.0, add), None);
+    }
+
+    #[test]
+    fn as_f64_vec() {
+        assert_eq!(Json::from_str("[1, 2, 3]").unwrap().as_f64_vec(), Some(vec![1.0, 2.0, 3.0]));
+        assert_eq!(Json::from_str("[1, 2.0, -3e-1]").unwrap().as_f64_vec(), Some(vec![1.0, 2.0, -0.3]));
+        assert_eq!(Json::from_str("[]").unwrap().as_f64_vec(), Some(vec![]));
+        assert_eq!(Json::from_str("[1, \"2\", 3]").unwrap().as_f64_vec(), None);
+        assert_eq!(Json::from_str("[1, null]").unwrap().as_f64_vec(), None);
+        assert_eq!(Json::from_str("{\"a\": 1}").unwrap().as_f64_vec(), None);
+    }
+
+    #[test]
+    fn numeric_aggregates() {
+        let arr = Json::from_str("[1, 2, 3.5]").unwrap();
+        assert_eq!(arr.sum_f64(), Some(6.5));
+        assert_eq!(arr.min_f64(), Some(1.0));
+        assert_eq!(arr.max_f64(), Some(3.5));
+
+        let arr = Json::from_str("[-1e2, 7]").unwrap();
+        assert_eq!(arr.min_f64(), Some(-100.0));
+        assert_eq!(arr.max_f64(), Some(7.0));
+
+        let empty = Json::from_str("[]").unwrap();
+        assert_eq!(empty.sum_f64(), Some(0.0));
+        assert_eq!(empty.min_f64(), None);
+        assert_eq!(empty.max_f64(), None);
+
+        let mixed = Json::from_str("[1, \"2\"]").unwrap();
+        assert_eq!(mixed.sum_f64(), None);
+        assert_eq!(mixed.min_f64(), None);
+        assert_eq!(mixed.max_f64(), None);
+
+        assert_eq!(Json::from(5).sum_f64(), None);
+    }
+
+    #[test]
+    fn contains() {
+        let obj = Json::from_str("{\"a\": [1, {\"b\": \"x\", \"c\": null}], \"d\": {\"e\": [true]}}").unwrap();
+
+        assert!(obj.contains_value(&Json::from("x")));
+        assert!(obj.contains_value(&Json::from(true)));
+        assert!(obj.contains_value(&Json::from(())));
+        assert!(obj.contains_value(&Json::from_str("[true]").unwrap()));
+        assert!(obj.contains_value(&obj));
+        assert!(!obj.contains_value(&Json::from("b")));
+        assert!(!obj.contains_value(&Json::from(false)));
+        assert!(!obj.contains_value(&Json::from_str("1.0").unwrap()));
+
+        assert!(obj.contains_key("a"));
+        assert!(obj.contains_key("c"));
+        assert!(obj.contains_key("e"));
+        assert!(!obj.contains_key("x"));
+        assert!(!Json::from("a").contains_key("a"));
+    }
+
+    #[test]
+    fn canonical_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        fn hash(s: &str) -> u64 {
+            let mut h = DefaultHasher::new();
+            Json::from_str(s).unwrap().canonical_hash(&mut h);
+            h.finish()
+        }
+
+        let base = hash("{\"a\": 1, \"b\": [true, {\"c\": null, \"d\": \"x\"}]}");
+        assert_eq!(hash("{\"b\":[true,{\"d\":\"x\",\"c\":null}],\"a\":1}"), base);
+        assert_eq!(hash("{\n  \"b\" : [ true , { \"d\" : \"x\" , \"c\" : null } ] ,\n  \"a\" : 1\n}"), base);
+        assert_eq!(hash("{\"a\": 0, \"b\": [true, {\"c\": null, \"d\": \"x\"}], \"a\": 1}"), base);
+
+        assert!(hash("{\"a\": 2, \"b\": [true, {\"c\": null, \"d\": \"x\"}]}") != base);
+        assert!(hash("{\"a\": 1, \"b\": [{\"c\": null, \"d\": \"x\"}, true]}") != base);
+        assert!(hash("{\"a\": 1}") != hash("{\"a\": \"1\"}"));
+        assert!(hash("[]") != hash("{}"));
+
+        // The exact bytes hashed do not depend on the `utf16` feature
+        #[derive(Default)]
+        struct Recorder(Vec<u8>);
+        impl Hasher for Recorder {
+            fn write(&mut self, bytes: &[u8]) { self.0.extend_from_slice(bytes); }
+            fn finish(&self) -> u64 { 0 }
+        }
+        let mut rec = Recorder::default();
+        Json::from_str("{\"z\": \"caf\u{e9}\", \"a\": [1]}").unwrap().canonical_hash(&mut rec);
+        assert_eq!(rec.0, b"{\"a\": [1], \"z\": \"caf\\u00e9\"}".to_vec());
+    }
+
+    #[test]
+    fn sort_keys() {
+        let mut obj = Json::from_str("{\"b\": [{\"z\": 1, \"y\": 2}], \"a\": {\"d\": 3, \"c\": 4}, \"b\": 5}").unwrap();
+        obj.sort_keys();
+        assert_eq!(obj.to_string(), "{\"a\": {\"c\": 4, \"d\": 3}, \"b\": [{\"y\": 2, \"z\": 1}], \"b\": 5}");
+    }
+
+    #[test]
+    fn sort_keys_by() {
+        let schema = ["id", "name", "address", "street", "city"];
+        let rank = |key: &str| schema.iter().position(|k| *k == key).unwrap_or(schema.len());
+
+        let mut obj = Json::from_str("{\"extra\": 0, \"address\": {\"city\": \"c\", \"zip\": 1, \"street\": \"s\"}, \"name\": \"n\", \"others\": [{\"name\": \"m\", \"id\": 2}], \"id\": 1}").unwrap();
+        obj.sort_keys_by(|a, b| rank(&a.0).cmp(&rank(&b.0)));
+        assert_eq!(obj.to_string(), "{\"id\": 1, \"name\": \"n\", \"address\": {\"street\": \"s\", \"city\": \"c\", \"zip\": 1}, \"extra\": 0, \"others\": [{\"id\": 2, \"name\": \"m\"}]}");
+
+        // Members can be ordered by value
+        let mut obj = Json::from_str("{\"a\": 3, \"b\": 1, \"c\": 2}").unwrap();
+        obj.sort_keys_by(|a, b| a.1.as_i128().cmp(&b.1.as_i128()));
+        assert_eq!(obj.to_string(), "{\"b\": 1, \"c\": 2, \"a\": 3}");
+    }
+
+    #[test]
+    fn canonicalize_in_place() {
+        let canonical = "{\"a\": {\"c\": 4, \"d\": [{\"x\": 1, \"y\": 2}]}, \"b\": 5}";
+        let mut obj = Json::from_str(canonical).unwrap();
+        assert_eq!(obj.canonicalize_in_place(), 0);
+        assert_eq!(obj.to_string(), canonical);
+
+        // Two objects reordered, two duplicates removed
+        let mut obj = Json::from_str("{\"b\": 1, \"a\": {\"d\": [{\"y\": 2, \"x\": 1}], \"c\": 4, \"c\": 4}, \"b\": 5}").unwrap();
+        assert_eq!(obj.canonicalize_in_place(), 5);
+        assert_eq!(obj.to_string(), canonical);
+        assert_eq!(obj.canonicalize_in_place(), 0);
+
+        // Removing a duplicate alone, without reordering, is a change
+        let mut obj = Json::from_str("[{\"a\": 1, \"a\": 2, \"a\": 3}]").unwrap();
+        assert_eq!(obj.canonicalize_in_place(), 2);
+        assert_eq!(obj.to_string(), "[{\"a\": 3}]");
+    }
+
+    #[test]
+    fn sorted_entries() {
+        let obj = Json::from_str("{\"b\": 1, \"c\": 2, \"a\": 3, \"b\": 4}").unwrap();
+        let sorted = obj.sorted_entries();
+        let keys: Vec<&str> = sorted.iter().map(|e| e.0).collect();
+        assert_eq!(keys, vec!["a", "b", "b", "c"]);
+        assert_eq!(sorted[1].1.num(), Some("1"));
+        assert_eq!(sorted[2].1.num(), Some("4"));
+        // Original order untouched
+        assert_eq!(obj.to_string(), "{\"b\": 1, \"c\": 2, \"a\": 3, \"b\": 4}");
+
+        assert!(Json::from_str("[1, 2]").unwrap().sorted_entries().is_empty());
+    }
+
+    #[test]
+    fn into_string() {
+        let s: String = Json::from("x").into();
+        assert_eq!(s, "\"x\"");
+        let obj = Json::from_str("{\"key\": [1, \"two\", null]}").unwrap();
+        let s: String = (&obj).into();
+        assert_eq!(s, "{\"key\": [1, \"two\", null]}");
+        assert_eq!(Json::from_str(&s).unwrap(), obj);
+    }
+
+    #[test]
+    fn coerce_numbers() {
+        let mut json = Json::from_str("[1.0, 1E2, -0.50, 1e-7, 2.5e+21, 0.000001, 1e999, \"1.0\", {\"k\": 10.10}]").unwrap();
+        json.coerce_numbers();
+        assert_eq!(json.to_string(), "[1, 100, -0.5, 1e-7, 2.5e21, 0.000001, 1e999, \"1.0\", {\"k\": 10.1}]");
+    }
+
+    #[test]
+    #[cfg(feature = "decimal")]
+    fn coerce_numbers_decimal() {
+        let mut json = Json::from_str("[1.0, 1E2, -0.50, 1e-7, 12345678901234567890, 1e999, \"1.0\", {\"k\": 10.10}]").unwrap();
+        json.coerce_numbers_decimal();
+        assert_eq!(json.to_string(), "[1, 100, -0.5, 0.0000001, 12345678901234567890, 1e999, \"1.0\", {\"k\": 10.1}]");
+    }
+
+    #[test]
+    fn string_literal() {
+        use to_json_string_literal;
+
+        assert_eq!(to_json_string_literal(""), "\"\"");
+        assert_eq!(to_json_string_literal("plain"), "\"plain\"");
+        assert_eq!(to_json_string_literal("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(to_json_string_literal("C:\\dir"), "\"C:\\\\dir\"");
+        assert_eq!(to_json_string_literal("a\nb\tc\r"), "\"a\\nb\\tc\\r\"");
+        #[cfg(feature = "utf16")]
+        assert_eq!(to_json_string_literal("caf\u{e9} \u{1d11e}"), "\"caf\\u00e9 \\ud834\\udd1e\"");
+        #[cfg(not(feature = "utf16"))]
+        assert_eq!(to_json_string_literal("caf\u{e9} \u{1d11e}"), "\"caf\u{e9} \u{1d11e}\"");
+
+        let s = "mixed \"\\\u{1}/";
+        assert_eq!(to_json_string_literal(s), Json::from(s).to_string());
+    }
+
+    #[test]
+    fn escape_into() {
+        use {escape_into, escape_into_vec, to_json_string_literal};
+
+        let parts = ["a", "", "say \"hi\"", "tab\there", "caf\u{e9}"];
+        let mut out = String::from("[");
+        let mut out_vec = b"[".to_vec();
+        let mut expected = String::from("[");
+        for part in &parts {
+            escape_into(part, &mut out);
+            escape_into_vec(part, &mut out_vec);
+            expected.push_str(&to_json_string_literal(part));
+        }
+        assert_eq!(out, expected);
+        assert_eq!(out_vec, expected.as_bytes());
+        #[cfg(feature = "utf16")]
+        assert_eq!(out, "[\"a\"\"\"\"say \\\"hi\\\"\"\"tab\\there\"\"caf\\u00e9\"");
+    }
+
+    #[test]
+    fn default() {
+        assert_eq!(Json::default(), Json::from_str("null").unwrap());
+
+        #[derive(Default)]
+        struct Wrapper { json: Json }
+        assert!(Wrapper::default().json.null().is_some());
+    }
+
+    #[test]
+    fn parse_bytes_regressions() {
+        use {parse_bytes, Error, ErrorInner};
+        use parser::ErrorType;
+
+        fn assert_error(input: &[u8], check: fn(&ErrorType) -> bool) {
+            match parse_bytes(input) {
+                Err(Error(ErrorInner::Parser(e))) => assert!(check(e.kind().innermost()), "{:?}: {}", input, e),
+                _ => panic!("wrong error return type for {:?}", input),
+            }
+        }
+
+        // Deep nesting, which once overflowed the stack
+        let mut deep = vec![b'['; 100_000];
+        assert!(parse_bytes(&deep).is_err());
+        deep.extend(vec![b']'; 100_000]);
+        assert!(parse_bytes(&deep).is_err());
+        assert!(parse_bytes(&vec![b'{'; 100_000]).is_err());
+        assert!(parse_bytes(b"{\"a\": {\"a\": {\"a\": {\"a\": {\"a\": [").is_err());
+
+        // Invalid UTF-8
+        assert_error(b"\"\xff\xfe\"", |e| matches!(*e, ErrorType::InvalidUtf8));
+        assert_error(b"\"\xc3\"", |e| matches!(*e, ErrorType::InvalidUtf8));
+        assert!(parse_bytes(b"\xff").is_err());
+        assert!(parse_bytes(b"[\xe2\x82]").is_err());
+
+        // Lone and misordered surrogates
+        assert_error(b"\"\\ud800\"", |e| matches!(*e, ErrorType::UnpairedSurrogate));
+        assert_error(b"\"\\ud800\\u0041\"", |e| matches!(*e, ErrorType::UnpairedSurrogate));
+        assert_eq!(parse_bytes(b"\"\\ud834\\udd1e\"").unwrap(), Json::from("\u{1d11e}"));
+        assert!(parse_bytes(b"\"\\udd1e\\ud834\"").is_err());
+        assert!(parse_bytes(b"\"\\ud834\\").is_err());
+
+        // Huge numbers
+        assert!(parse_bytes(b"1e99999999999999999999").is_ok());
+        let mut huge = vec![b'9'; 100_000];
+        assert!(parse_bytes(&huge).is_ok());
+        huge.push(b'.');
+        assert!(parse_bytes(&huge).is_ok());
+
+        // Empty and truncated input
+        assert!(parse_bytes(b"").is_err());
+        assert!(parse_bytes(b"[1, {\"").is_err());
+        assert!(parse_bytes(b"\"\\u12").is_err());
+    }
+
+    #[test]
+    fn error_to_json() {
+        let err = Json::from_str("[1, 2").unwrap_err();
+        assert_eq!(err.to_json().get("code").and_then(Json::string), Some("unexpected_eof"));
+        assert_eq!(err.to_json().get("byte_offset"), Some(&Json::from(5usize)));
+
+        let err = Json::from(()).require_object().unwrap_err();
+        assert_eq!(err.to_json().to_string(), "{\"line\": null, \"col\": null, \"byte_offset\": null, \
+                                               \"code\": \"other\", \"message\": \"expected an object, found null\"}");
+    }
+
+    #[test]
+    fn with() {
+        let built = Json::empty_object()
+            .with("name", "widget")
+            .with("count", 3u32)
+            .with("tags", Json::empty_array().with_pushed("a").with_pushed(Json::from(())))
+            .with("dims", Json::empty_object().with("w", 1.5).with("h", 2u8))
+            .with(String::from("count"), 4u32);
+        let parsed = Json::from_str("{\"name\": \"widget\", \"count\": 4, \"tags\": [\"a\", null], \"dims\": {\"w\": 1.5, \"h\": 2}}").unwrap();
+        assert_eq!(built, parsed);
+
+        assert_eq!(Json::empty_object().to_string(), "{}");
+        assert_eq!(Json::empty_array().to_string(), "[]");
+    }
+
+    #[test]
+    fn get_index() {
+        let arr = Json::from_str("[1, [2, 3]]").unwrap();
+        assert_eq!(arr.get_index(0), Some(&Json::from(1u8)));
+        assert_eq!(arr.get_index(1).and_then(|v| v.get_index(1)), Some(&arr[1][1]));
+        assert_eq!(arr.get_index(2), None);
+        assert_eq!(Json::from_str("{\"0\": 1}").unwrap().get_index(0), None);
+        assert_eq!(Json::from("ab").get_index(0), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_range() {
+        let _ = &Json::from_str("[1, 2]").unwrap()[2];
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_non_array() {
+        let _ = &Json::from_str("{\"0\": 1}").unwrap()[0];
+    }
+
+    #[test]
+    #[should_panic]
+    fn with_non_object() {
+        let _ = Json::empty_array().with("a", 1u8);
+    }
+
+    #[test]
+    fn matches_shape() {
+        let shape = Json::from_str("{\"id\": \"number\", \"name\": \"string\", \"tags\": [\"string\"], \
+                                     \"owner\": {\"email\": \"string\", \"admin\": \"bool\"}, \"extra\": \"any\"}").unwrap();
+        let doc = Json::from_str("{\"id\": 7, \"name\": \"w\", \"tags\": [\"a\", \"b\"], \
+                                   \"owner\": {\"email\": \"e@x\", \"admin\": false, \"age\": 3}, \"extra\": null}").unwrap();
+        assert!(doc.matches_shape(&shape));
+
+        // A missing key, at the top level or nested
+        for ptr in &["/name", "/owner/admin", "/extra"] {
+            let mut missing = doc.clone();
+            let (parent, key) = ptr.split_at(ptr.rfind('/').unwrap());
+            if let JsonInner::Object(ref mut v) = missing.pointer_mut(parent).unwrap().0 {
+                v.retain(|m| m.0 != key[1..]);
+            }
+            assert!(!missing.matches_shape(&shape), "{}", ptr);
+        }
+
+        // Wrong leaf types
+        for &(ptr, val) in &[("/id", "\"7\""), ("/tags/1", "1"), ("/owner/admin", "null"), ("/tags", "{}")] {
+            let mut wrong = doc.clone();
+            wrong.replace_pointer(ptr, Json::from_str(val).unwrap()).unwrap();
+            assert!(!wrong.matches_shape(&shape), "{}", ptr);
+        }
+
+        let either = Json::from_str("[\"number\", {\"n\": \"number\"}]").unwrap();
+        assert!(Json::from_str("[1, {\"n\": 2}]").unwrap().matches_shape(&either));
+        assert!(!Json::from_str("[1, \"2\"]").unwrap().matches_shape(&either));
+        assert!(Json::from_str("[1, \"2\"]").unwrap().matches_shape(&Json::from_str("[]").unwrap()));
+        assert!(!Json::from(1).matches_shape(&Json::from("integer")));
+        assert!(!Json::from(1).matches_shape(&Json::from(1)));
+    }
+
+    #[test]
+    fn is_homogeneous_array() {
+        let check = |s: &str| Json::from_str(s).unwrap().is_homogeneous_array();
+        assert_eq!(check("[1, 2.5, -3e1]"), Some(::JsonType::Number));
+        assert_eq!(check("[\"a\"]"), Some(::JsonType::String));
+        assert_eq!(check("[[1], [\"a\"], []]"), Some(::JsonType::Array));
+        assert_eq!(check("[true, false]"), Some(::JsonType::Bool));
+        assert_eq!(check("[1, \"2\"]"), None);
+        assert_eq!(check("[null, {}]"), None);
+        assert_eq!(check("[]"), None);
+        assert_eq!(check("{\"a\": 1}"), None);
+        assert_eq!(check("1"), None);
+    }
+
+    #[test]
+    fn count_by_type() {
+        let json = Json::from_str("{\"a\": [1, 2.5, \"x\", null, [true, {}]], \"b\": {\"c\": false, \"d\": \"y\"}}").unwrap();
+        let counts = json.count_by_type();
+        assert_eq!(counts, [1, 2, 2, 2, 2, 3]);
+        assert_eq!(counts[::JsonType::Object as usize], 3);
+        assert_eq!(counts.iter().sum::<usize>(), 12);
+
+        assert_eq!(Json::from("s").count_by_type(), [0, 0, 0, 1, 0, 0]);
+
+        let mut deep = Json::from(());
+        for _ in 0..100_000 {
+            deep = Json::from(vec![deep]);
+        }
+        assert_eq!(deep.count_by_type(), [1, 0, 0, 0, 100_000, 0]);
+        // Dropping is recursive, so leak rather than overflow the stack
+        ::std::mem::forget(deep);
+    }
+
+    #[test]
+    fn get() {
+        let obj = Json::from_str("{\"a\": 1, \"b\": 2, \"a\": 3, \"c\": null}").unwrap();
+        assert_eq!(obj.get("b"), Some(&Json::from(2u8)));
+        assert_eq!(obj.get("c"), Some(&Json::from(())));
+        assert_eq!(obj.get("d"), None);
+        // The first of duplicate keys wins, as with indexing
+        assert_eq!(obj.get("a"), Some(&Json::from(1u8)));
+        assert_eq!(obj.get("a"), Some(&obj["a"]));
+
+        assert_eq!(obj.get_all("a").collect::<Vec<_>>(), vec![&Json::from(1u8), &Json::from(3u8)]);
+        assert_eq!(obj.get_all("b").count(), 1);
+        assert_eq!(obj.get_all("d").count(), 0);
+
+        // Non-objects have no members
+        let arr = Json::from_str("[\"a\", {\"a\": 1}]").unwrap();
+        assert_eq!(arr.get("a"), None);
+        assert_eq!(arr.get_all("a").count(), 0);
+        assert_eq!(Json::from("a").get_all("a").count(), 0);
+    }
+
+    #[test]
+    fn get_or() {
+        let config = Json::from_str("{\"port\": 8080, \"host\": null}").unwrap();
+        let default = Json::from(80u64);
+        assert_eq!(config.get_or("port", &default), &Json::from(8080u64));
+        assert_eq!(config.get_or("timeout", &default), &default);
+        // A member which is present but null is still present
+        assert_eq!(config.get_or("host", &default), &Json::from(()));
+        // Non-objects have no members
+        assert_eq!(Json::from(vec![config.clone()]).get_or("port", &default), &default);
+    }
+
+    #[test]
+    fn require() {
+        let obj = Json::from_str("{\"key\": 1}").unwrap();
+        assert_eq!(obj.clone().require_object().unwrap(), obj);
+        let err = obj.require_array().unwrap_err();
+        assert_eq!(err.to_string(), "expected an array, found an object");
+
+        let arr = Json::from_str("[1]").unwrap();
+        assert_eq!(arr.clone().require_array().unwrap(), arr);
+        let err = arr.require_object().unwrap_err();
+        assert_eq!(err.to_string(), "expected an object, found an array");
+
+        let err = Json::from_str("\"x\"").unwrap().require_object().unwrap_err();
+        assert_eq!(err.to_string(), "expected an object, found a string");
+        let err = Json::from_str("null").unwrap().require_array().unwrap_err();
+        assert_eq!(err.to_string(), "expected an array, found null");
+    }
+
+    #[test]
+    fn ensure_depth() {
+        let json = Json::from_str("[1, {\"a\": [[]], \"b\": 2}]").unwrap();
+        assert!(json.ensure_depth(4).is_ok());
+        assert!(json.ensure_depth(10).is_ok());
+        let err = json.ensure_depth(3).unwrap_err();
+        assert_eq!(err.to_string(), "nesting depth limit of 3 exceeded");
+
+        assert!(Json::from_str("\"x\"").unwrap().ensure_depth(0).is_ok());
+        assert!(Json::from_str("{}").unwrap().ensure_depth(0).is_err());
+        assert!(Json::from_str("{}").unwrap().ensure_depth(1).is_ok());
+
+        // Agrees with the parser's limit
+        let mut deep = Json::from(());
+        for _ in 0..::parser::DEFAULT_MAX_DEPTH {
+            deep = Json::from(vec![deep]);
+        }
+        assert!(Json::from_str(&deep.to_string()).is_ok());
+        assert!(deep.ensure_depth(::parser::DEFAULT_MAX_DEPTH).is_ok());
+        let deeper = Json::from(vec![deep]);
+        assert!(Json::from_str(&deeper.to_string()).is_err());
+        assert!(deeper.ensure_depth(::parser::DEFAULT_MAX_DEPTH).is_err());
+    }
+
+    #[test]
+    fn f64_lossy() {
+        assert_eq!(Json::from_str("-1.5e2").unwrap().as_f64_lossy(), -150.0);
+        assert_eq!(Json::from_str("0").unwrap().as_f64_lossy(), 0.0);
+        assert!(Json::from_str("\"1.5\"").unwrap().as_f64_lossy().is_nan());
+        assert!(Json::from_str("null").unwrap().as_f64_lossy().is_nan());
+        assert!(Json::from_str("[1]").unwrap().as_f64_lossy().is_nan());
+        // The parser will not produce these, but other constructors might
+        assert!(Json(JsonInner::Number("NaN".to_owned())).as_f64_lossy().is_nan());
+        assert_eq!(Json(JsonInner::Number("Infinity".to_owned())).as_f64_lossy(), f64::INFINITY);
+        assert!(Json(JsonInner::Number("1.2.3".to_owned())).as_f64_lossy().is_nan());
+    }
+
     #[test]
     fn from() {
         assert_eq!(Json::from_str("123").unwrap(), From::from(123u8));
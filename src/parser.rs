@@ -15,9 +15,10 @@
 //! # Parsing support
 //!
 
-use std::{error, char, fmt, io, num};
+use std::{error, char, fmt, io, num, str};
+use std::collections::HashSet;
 
-use {Json, JsonInner};
+use {Json, JsonInner, JsonType, PathSegment};
 
 /// The type of a Json parsing error
 #[derive(Debug)]
@@ -34,6 +35,19 @@ pub enum ErrorType {
     UnexpectedEOF,
     /// bad character encountered when parsing some data
     UnexpectedCharacter(char),
+    /// an object key was not followed by a `:`
+    ExpectedColon,
+    /// the grammar required a specific token, described by `expected`,
+    /// but a different character was found
+    Expected {
+        /// the token or tokens that would have been accepted
+        expected: &'static str,
+        /// the character found instead
+        found: char,
+    },
+    /// end-of-file reached inside a string, which started at the given
+    /// line and column
+    UnterminatedString(usize, usize),
     /// a number contained a bad or misplaced character
     MalformedNumber,
     /// an escape sequence was invalid
@@ -44,8 +58,40 @@ pub enum ErrorType {
     Unicode(num::ParseIntError),
     /// UTF-16 sequence with unpaired surrogate
     UnpairedSurrogate,
+    /// a string contained bytes which are not valid UTF-8
+    InvalidUtf8,
+    /// arrays and objects were nested more deeply than allowed
+    DepthLimitExceeded,
+    /// a number literal was longer than allowed
+    NumberTooLong,
+    /// an object key was longer than allowed
+    KeyTooLong,
+    /// the given key appeared twice in one object, which was disallowed
+    DuplicateKey(String),
+    /// an integer was outside the range which is exactly representable as
+    /// an IEEE double, which was disallowed
+    UnsafeInteger,
+    /// the top-level value was of type `found`, which is not one of the
+    /// types allowed by `Parser::parse_expecting`
+    UnexpectedType {
+        /// the types which would have been accepted
+        allowed: Vec<JsonType>,
+        /// the type of the value parsed
+        found: JsonType,
+    },
+    /// the value for the given object key failed to parse. Every error
+    /// inside an object value is wrapped like this, so use `innermost` to
+    /// see what the error was.
+    InObjectValue(String, Box<ErrorType>),
+    /// the array element with the given index failed to parse. As with
+    /// `InObjectValue`, every error inside an array element is wrapped
+    /// like this; `innermost` removes the wrapping.
+    InArrayElement(usize, Box<ErrorType>),
     /// some sort of IO error
-    Io(io::Error)
+    Io(io::Error),
+    /// an error of some other type from the input iterator, which can be
+    /// recovered with `downcast`
+    Source(Box<dyn error::Error + Send + Sync>)
 }
 
 impl From<num::ParseIntError> for ErrorType {
@@ -56,6 +102,30 @@ impl From<io::Error> for ErrorType {
     fn from(e: io::Error) -> ErrorType { ErrorType::Io(e) }
 }
 
+/// Wrap an error from the input iterator, keeping IO errors as `Io`
+fn source_error<E: error::Error + Send + Sync + 'static>(e: E) -> ErrorType {
+    let e: Box<dyn error::Error + Send + Sync> = Box::new(e);
+    match e.downcast::<io::Error>() {
+        Ok(e) => ErrorType::Io(*e),
+        Err(e) => ErrorType::Source(e),
+    }
+}
+
+/// The number of context wrappers around an error
+fn context_levels(mut e: &ErrorType) -> usize {
+    let mut ret = 0;
+    while let ErrorType::InObjectValue(_, ref inner) | ErrorType::InArrayElement(_, ref inner) = *e {
+        ret += 1;
+        e = inner;
+    }
+    ret
+}
+
+/// How many levels of context an error message spells out in full. Any
+/// further out are given just as a pointer, so that an error deep inside
+/// nesting does not make for a message kilobytes long.
+const MAX_CONTEXT_SHOWN: usize = 3;
+
 /// A macro which acts like try! but attaches line/column info to the error
 macro_rules! try_at(
     ($s:expr, $e:expr) => (
@@ -74,68 +144,295 @@ macro_rules! try_at(
 pub struct Error {
     line: usize,
     col: usize,
+    // Byte offset of the same position, counting from 0, if known
+    offset: Option<usize>,
     error: ErrorType
 }
 
 impl From<ErrorType> for Error {
-    fn from(e: ErrorType) -> Error { Error { line: 1, col: 1, error: e } }
+    fn from(e: ErrorType) -> Error { Error { line: 1, col: 1, offset: None, error: e } }
 }
 
-impl fmt::Display for Error {
+impl Error {
+    /// Construct an error at the given line and column, which count from 1,
+    /// e.g. to report a problem found in a parsed value at a position
+    /// recorded while parsing
+    pub fn new(line: usize, col: usize, kind: ErrorType) -> Error {
+        Error { line, col, offset: None, error: kind }
+    }
+
+    /// Construct an error as `new` does, also giving the byte offset of the
+    /// same position, which counts from 0
+    pub fn new_at(line: usize, col: usize, offset: usize, kind: ErrorType) -> Error {
+        Error { line, col, offset: Some(offset), error: kind }
+    }
+
+    /// The error as a Json object, for tools which display diagnostics:
+    /// `line` and `col`, which count from 1, `byte_offset`, which counts
+    /// from 0 and is null for errors constructed with `new`, `code`, from
+    /// `ErrorType::code`, and `message`, the same text as `Display` gives.
+    pub fn to_json(&self) -> Json {
+        let offset = match self.offset {
+            Some(offset) => Json::from(offset),
+            None => Json::from(()),
+        };
+        Json(JsonInner::Object(vec![
+            ("line".to_owned(), Json::from(self.line)),
+            ("col".to_owned(), Json::from(self.col)),
+            ("byte_offset".to_owned(), offset),
+            ("code".to_owned(), Json::from(self.error.code())),
+            ("message".to_owned(), Json::from(self.to_string())),
+        ]))
+    }
+
+    /// The type of error, wrapped in `ErrorType::InObjectValue` and
+    /// `ErrorType::InArrayElement` context if it happened inside an object
+    /// or array; `ErrorType::innermost` removes that.
+    pub fn kind(&self) -> &ErrorType {
+        &self.error
+    }
+
+    /// Wrap the error type in some context, keeping the position
+    fn in_context<F: FnOnce(ErrorType) -> ErrorType>(self, f: F) -> Error {
+        Error { line: self.line, col: self.col, offset: self.offset, error: f(self.error) }
+    }
+}
+
+impl fmt::Display for ErrorType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.error {
-            ErrorType::UnexpectedCharacter(c) => write!(f, "{}:{}: unexpected character {}", self.line, self.col, c),
-            ErrorType::Io(ref e) => write!(f, "{}:{}: {}", self.line, self.col, e),
-            ErrorType::Unicode(ref e) => write!(f, "{}:{}: {}", self.line, self.col, e),
+        match *self {
+            ErrorType::UnexpectedCharacter(c) => write!(f, "unexpected character {}", c),
+            ErrorType::Expected { expected, found } if found.is_control() => {
+                write!(f, "expected {}, found `{}`", expected, found.escape_debug())
+            }
+            ErrorType::Expected { expected, found } => write!(f, "expected {}, found `{}`", expected, found),
+            ErrorType::Io(ref e) => write!(f, "{}", e),
+            ErrorType::Source(ref e) => write!(f, "{}", e),
+            ErrorType::Unicode(ref e) => write!(f, "{}", e),
             ErrorType::MissingField(ref s) => write!(f, "missing field `{}`", s),
             ErrorType::UnknownField(ref s) => write!(f, "unknown field `{}`", s),
+            ErrorType::DuplicateKey(ref s) => write!(f, "duplicate object key `{}`", s),
             ErrorType::Other(ref s) => write!(f, "syntax error: {}", s),
-            _ => write!(f, "{}:{}: {}", self.line, self.col, error::Error::description(self))
+            ErrorType::UnexpectedType { ref allowed, found } => {
+                f.write_str("expected ")?;
+                for (n, ty) in allowed.iter().enumerate() {
+                    if n > 0 {
+                        f.write_str(if n + 1 == allowed.len() { " or " } else { ", " })?;
+                    }
+                    f.write_str(ty.describe())?;
+                }
+                write!(f, ", found {}", found.describe())
+            }
+            ErrorType::InObjectValue(..) | ErrorType::InArrayElement(..) if context_levels(self) > MAX_CONTEXT_SHOWN => {
+                f.write_str("error parsing value at ")?;
+                let mut e = self;
+                for _ in MAX_CONTEXT_SHOWN..context_levels(self) {
+                    e = match *e {
+                        ErrorType::InObjectValue(ref key, ref inner) => {
+                            write!(f, "/{}", key.replace('~', "~0").replace('/', "~1"))?;
+                            inner
+                        }
+                        ErrorType::InArrayElement(idx, ref inner) => {
+                            write!(f, "/{}", idx)?;
+                            inner
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+                write!(f, ": {}", e)
+            }
+            ErrorType::InObjectValue(ref key, ref e) => write!(f, "error parsing value for key `{}`: {}", key, e),
+            ErrorType::InArrayElement(idx, ref e) => write!(f, "error parsing array element {}: {}", idx, e),
+            _ => f.write_str(self.description())
         }
     }
 }
 
-impl error::Error for Error {
-    fn cause(&self) -> Option<&error::Error> {
-        match self.error {
-            ErrorType::Io(ref e) => Some(e),
-            ErrorType::Unicode(ref e) => Some(e),
-            _ => None
+impl ErrorType {
+    /// The error with any `InObjectValue` and `InArrayElement` context
+    /// removed, i.e. what actually went wrong. Errors inside arrays and
+    /// objects are wrapped in that context, so match on this rather than
+    /// on the error itself to find e.g. an `UnexpectedEOF` wherever it is.
+    pub fn innermost(&self) -> &ErrorType {
+        let mut e = self;
+        while let ErrorType::InObjectValue(_, ref inner) | ErrorType::InArrayElement(_, ref inner) = *e {
+            e = inner;
+        }
+        e
+    }
+
+    /// A short identifier for the kind of error, which unlike the message
+    /// will not change between versions, for tools to match on. Context
+    /// from `InObjectValue` and `InArrayElement` is skipped, giving the code
+    /// of the error inside.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ErrorType::MissingField(_) => "missing_field",
+            ErrorType::UnknownField(_) => "unknown_field",
+            ErrorType::Other(_) => "other",
+            ErrorType::ExpectedString => "expected_string",
+            ErrorType::UnexpectedEOF => "unexpected_eof",
+            ErrorType::UnexpectedCharacter(_) => "unexpected_character",
+            ErrorType::ExpectedColon => "expected_colon",
+            ErrorType::Expected { .. } => "expected_token",
+            ErrorType::UnterminatedString(..) => "unterminated_string",
+            ErrorType::MalformedNumber => "malformed_number",
+            ErrorType::MalformedEscape => "malformed_escape",
+            ErrorType::UnknownIdent => "unknown_ident",
+            ErrorType::Unicode(_) => "bad_unicode_escape",
+            ErrorType::UnpairedSurrogate => "unpaired_surrogate",
+            ErrorType::InvalidUtf8 => "invalid_utf8",
+            ErrorType::DepthLimitExceeded => "depth_limit_exceeded",
+            ErrorType::NumberTooLong => "number_too_long",
+            ErrorType::KeyTooLong => "key_too_long",
+            ErrorType::DuplicateKey(_) => "duplicate_key",
+            ErrorType::UnsafeInteger => "unsafe_integer",
+            ErrorType::UnexpectedType { .. } => "unexpected_type",
+            ErrorType::InObjectValue(_, ref e) => e.code(),
+            ErrorType::InArrayElement(_, ref e) => e.code(),
+            ErrorType::Io(_) => "io",
+            ErrorType::Source(_) => "input_error",
         }
     }
 
     fn description(&self) -> &str {
-        match self.error {
+        match *self {
             ErrorType::ExpectedString => "expected string",
             ErrorType::UnexpectedEOF => "unexpected eof",
+            ErrorType::UnterminatedString(..) => "unterminated string",
             ErrorType::UnexpectedCharacter(_) => "bad character",
+            ErrorType::ExpectedColon => "expected `:` after object key",
+            ErrorType::Expected { .. } => "unexpected token",
             ErrorType::MalformedEscape => "bad escape",
             ErrorType::MalformedNumber => "malformed number",
             ErrorType::UnknownIdent => "unknown ident",
             ErrorType::Unicode(ref e) => error::Error::description(e),
             ErrorType::UnpairedSurrogate => "UTF-16 unpaired surrogate",
+            ErrorType::InvalidUtf8 => "invalid UTF-8 in string",
+            ErrorType::DepthLimitExceeded => "nesting depth limit exceeded",
+            ErrorType::NumberTooLong => "number too long",
+            ErrorType::KeyTooLong => "object key too long",
+            ErrorType::DuplicateKey(_) => "duplicate object key",
+            ErrorType::UnsafeInteger => "integer outside of interoperable range",
+            ErrorType::UnexpectedType { .. } => "value of unexpected type",
+            ErrorType::InObjectValue(_, ref e) => e.description(),
+            ErrorType::InArrayElement(_, ref e) => e.description(),
             ErrorType::Io(ref e) => error::Error::description(e),
+            ErrorType::Source(_) => "input error",
             ErrorType::MissingField(_) => "missing field",
             ErrorType::UnknownField(_) => "unknown field",
             ErrorType::Other(_) => "syntax/other error",
         }
     }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ErrorType::Io(ref e) => Some(e),
+            ErrorType::Source(ref e) => Some(&**e),
+            ErrorType::Unicode(ref e) => Some(e),
+            ErrorType::InObjectValue(_, ref e) => e.cause(),
+            ErrorType::InArrayElement(_, ref e) => e.cause(),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error {
+            // These are not produced by the parser and have no meaningful position
+            ErrorType::MissingField(_) | ErrorType::UnknownField(_) | ErrorType::Other(_) => {
+                fmt::Display::fmt(&self.error, f)
+            }
+            _ => write!(f, "{}:{}: {}", self.line, self.col, self.error)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        self.error.cause()
+    }
+
+    fn description(&self) -> &str {
+        self.error.description()
+    }
+}
+
+/// What to do with integers outside of `[-(2^53 - 1), 2^53 - 1]`, which
+/// RFC 8259 warns may not interoperate since many implementations store
+/// numbers as IEEE doubles
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnsafeIntegers {
+    /// Accept them; the number is kept as a string anyway
+    Allow,
+    /// Return an `ErrorType::UnsafeInteger` error
+    Reject,
+}
+
+/// The original text of a node captured by `Parser::parse_with_raw`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RawJson {
+    /// Path from the root of the document to the node
+    pub path: Vec<PathSegment>,
+    /// The node's source text, exactly as it appeared in the input
+    pub text: String,
 }
 
+/// Selects, by path, the nodes whose text `Parser::parse_with_raw` captures
+type CapturePredicate<'a> = &'a dyn Fn(&[PathSegment]) -> bool;
+
+/// The maximum nesting depth of arrays and objects accepted by default
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
 /// A structure capable of parsing binary ASCII data into a "JSON object",
 /// which is simply a tree of strings. Further parsing should be done by
 /// other layers.
-pub struct Parser<I: Iterator<Item=io::Result<u8>>> {
+///
+/// The input is usually read with `io::Read::bytes`, but any iterator of
+/// `Result<u8, E>` will do, using `Parser::from_fallible`. Errors which are
+/// not `io::Error`s are returned as `ErrorType::Source`, from which the
+/// original can be recovered.
+pub struct Parser<I: Iterator> {
     iter: I,
     peek: Option<u8>,
+    // Position counters only ever increase, one step per input byte, so on
+    // a hostile unbounded stream they saturate rather than wrap or panic.
     line: usize,
-    col: usize
+    col: usize,
+    offset: usize,
+    // Position of the last byte consumed, as opposed to merely peeked at
+    consumed_line: usize,
+    consumed_col: usize,
+    // Current and maximum nesting depth; `parse` recurses once per level
+    depth: usize,
+    max_depth: usize,
+    keywords: Vec<(String, Json)>,
+    strip_xssi_prefix: bool,
+    unsafe_integers: UnsafeIntegers,
+    max_number_len: usize,
+    max_key_len: usize,
+    coerce_scalar_keys: bool,
+    allow_unicode_whitespace: bool,
+    allow_leading_plus: bool,
+    reject_duplicate_keys: bool,
+    reject_control_characters: bool,
+    allow_trailing_commas: bool,
+    allow_comments: bool,
+    allow_single_quotes: bool,
+    // Byte span of each node parsed so far, in pre-order, if requested
+    spans: Option<Vec<(usize, usize)>>,
+    // The path to the current node, if capturing raw text, the text of the
+    // node being captured and all captures so far
+    path: Vec<PathSegment>,
+    raw: Option<Vec<u8>>,
+    captured: Vec<RawJson>,
 }
 
-impl<I: Iterator<Item=io::Result<u8>>> Iterator for Parser<I>  {
-    type Item = io::Result<u8>;
+impl<I: Iterator<Item=Result<u8, E>>, E> Iterator for Parser<I>  {
+    type Item = Result<u8, E>;
 
-    fn next(&mut self) -> Option<io::Result<u8>> {
+    fn next(&mut self) -> Option<Result<u8, E>> {
         match self.peek.take() {
             Some(ch) => Some(Ok(ch)),
             None => {
@@ -143,11 +440,12 @@ impl<I: Iterator<Item=io::Result<u8>>> Iterator for Parser<I>  {
                     None => None,
                     Some(Err(e)) => Some(Err(e)),
                     Some(Ok(ch)) => {
+                        self.offset = self.offset.saturating_add(1);
                         if ch == b'\n' {
                             self.col = 0;
-                            self.line += 1;
+                            self.line = self.line.saturating_add(1);
                         } else {
-                            self.col += 1;
+                            self.col = self.col.saturating_add(1);
                         }
                         self.peek = Some(ch);
                         Some(Ok(ch))
@@ -161,20 +459,221 @@ impl<I: Iterator<Item=io::Result<u8>>> Iterator for Parser<I>  {
 impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
     /// Construct a new parser, given a byte iterator as input
     pub fn new(iter: I) -> Parser<I> {
+        Parser::from_fallible(iter)
+    }
+}
+
+impl<I, E> Parser<I>
+    where I: Iterator<Item=Result<u8, E>>,
+          E: error::Error + Send + Sync + 'static
+{
+    /// Construct a new parser, given as input a byte iterator whose errors
+    /// are of some type other than `io::Error`
+    pub fn from_fallible(iter: I) -> Parser<I> {
         Parser {
             iter: iter,
             peek: None,
             line: 1,
             col: 0,
+            offset: 0,
+            consumed_line: 1,
+            consumed_col: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            keywords: vec![],
+            strip_xssi_prefix: false,
+            unsafe_integers: UnsafeIntegers::Allow,
+            max_number_len: usize::MAX,
+            max_key_len: usize::MAX,
+            coerce_scalar_keys: false,
+            allow_unicode_whitespace: false,
+            allow_leading_plus: false,
+            reject_duplicate_keys: false,
+            reject_control_characters: false,
+            allow_trailing_commas: false,
+            allow_comments: false,
+            allow_single_quotes: false,
+            spans: None,
+            path: vec![],
+            raw: None,
+            captured: vec![],
         }
     }
 
+    /// Switch to a new input, as if this were a fresh parser but keeping
+    /// all options and the capacity of internal buffers, so that many small
+    /// documents can be parsed without constructing a parser for each.
+    /// Positions start again from the first line.
+    pub fn reset(&mut self, iter: I) {
+        self.iter = iter;
+        self.peek = None;
+        self.line = 1;
+        self.col = 0;
+        self.offset = 0;
+        self.consumed_line = 1;
+        self.consumed_col = 0;
+        self.depth = 0;
+        self.path.clear();
+        self.raw = None;
+    }
+
+    /// Whether to skip the anti-XSSI prefix `)]}'` if it appears at the
+    /// very start of the input, as some web APIs prepend it to responses.
+    /// Off by default.
+    pub fn strip_xssi_prefix(&mut self, strip: bool) {
+        self.strip_xssi_prefix = strip;
+    }
+
+    /// Accept `spelling` as an alternate keyword which parses to `value`,
+    /// e.g. `nil` for `null` when reading legacy data. By default only the
+    /// standard `null`, `true` and `false` are accepted. The value may be
+    /// an array or object too, e.g. for a placeholder standing for some
+    /// default; it is cloned each time the keyword appears.
+    ///
+    /// Panics unless the spelling is an ASCII letter followed by ASCII
+    /// letters, digits or underscores, since only such a word is read as a
+    /// keyword.
+    pub fn add_keyword(&mut self, spelling: &str, value: Json) {
+        let mut bytes = spelling.bytes();
+        assert!(bytes.next().is_some_and(|b| b.is_ascii_alphabetic()) &&
+                bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_'),
+                "invalid keyword spelling `{}`", spelling);
+        self.keywords.push((spelling.to_owned(), value));
+    }
+
+    /// What to do with integer literals too large in magnitude to be exactly
+    /// represented by an IEEE double. Only literals without a fraction or
+    /// exponent are checked. The default is `UnsafeIntegers::Allow`.
+    pub fn warn_or_reject_unsafe_integers(&mut self, mode: UnsafeIntegers) {
+        self.unsafe_integers = mode;
+    }
+
+    /// Set the maximum nesting depth of arrays and objects. Deeper input
+    /// causes an `ErrorType::DepthLimitExceeded` error rather than
+    /// overflowing the stack, since parsing recurses once per level. The
+    /// default is `DEFAULT_MAX_DEPTH`; raising it far beyond that risks the
+    /// overflow this guards against, depending on the stack size.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// Set the maximum length, in bytes, of a number literal. Longer numbers
+    /// cause an `ErrorType::NumberTooLong` error as soon as the limit is
+    /// passed. By default there is no limit.
+    pub fn set_max_number_len(&mut self, len: usize) {
+        self.max_number_len = len;
+    }
+
+    /// Set the maximum length in bytes of an object key, after unescaping.
+    /// Longer keys cause an `ErrorType::KeyTooLong` error as soon as the
+    /// limit is passed, so a huge key is never held in memory. Keys coerced
+    /// from scalars are not counted. By default there is no limit.
+    pub fn set_max_key_len(&mut self, len: usize) {
+        self.max_key_len = len;
+    }
+
+    /// Whether to accept numbers, `true`, `false` and `null` as object keys,
+    /// as some producers emit, converting them to strings: `{1: "a"}` parses
+    /// as `{"1": "a"}`. Numbers keep their original text. Off by default,
+    /// since strict Json only allows string keys.
+    pub fn coerce_scalar_keys(&mut self, coerce: bool) {
+        self.coerce_scalar_keys = coerce;
+    }
+
+    /// Whether to treat Unicode space characters which often creep in by
+    /// copy-and-paste as whitespace between tokens: no-break spaces
+    /// (U+00A0, U+202F), the byte order mark (U+FEFF), the line and
+    /// paragraph separators (U+2028, U+2029) and the other space separators
+    /// (U+1680, U+2000 to U+200A, U+205F, U+3000). They must be encoded as
+    /// UTF-8. Off by default, since strict Json rejects them.
+    pub fn allow_unicode_whitespace(&mut self, allow: bool) {
+        self.allow_unicode_whitespace = allow;
+    }
+
+    /// Whether to accept a `+` sign at the start of a number, as in `+1` or
+    /// `+1.5e3`, which some producers emit. The sign is dropped, so the
+    /// number is stored, and serialized, as valid Json. Off by default,
+    /// since strict Json only allows `+` in exponents.
+    pub fn allow_leading_plus(&mut self, allow: bool) {
+        self.allow_leading_plus = allow;
+    }
+
+    /// Whether to fail with `ErrorType::DuplicateKey` when a key appears
+    /// twice in the same object, which RFC 8259 says should not happen but
+    /// does not forbid. The error is reported at the second occurrence.
+    /// Keys are compared after unescaping, so `"a"` and `"\u0061"` are the
+    /// same. Off by default, keeping every member as parsed.
+    pub fn reject_duplicate_keys(&mut self, reject: bool) {
+        self.reject_duplicate_keys = reject;
+    }
+
+    /// Whether to fail with `ErrorType::UnexpectedCharacter` on a raw
+    /// control character, NUL, tab and newline included, inside a string,
+    /// as RFC 8259 requires them to be escaped. Off by default, accepting
+    /// them as they are.
+    pub fn reject_control_characters(&mut self, reject: bool) {
+        self.reject_control_characters = reject;
+    }
+
+    /// Whether to accept a single comma after the last element of an array
+    /// or the last member of an object, as in `[1, 2,]`, which hand-edited
+    /// files often have. A comma alone, as in `[,]`, is still an error.
+    /// Off by default.
+    pub fn allow_trailing_commas(&mut self, allow: bool) {
+        self.allow_trailing_commas = allow;
+    }
+
+    /// Whether to skip `//` line comments and `/* */` block comments
+    /// wherever whitespace is allowed, as in JSON5 and many configuration
+    /// files. Block comments do not nest; one left open is an
+    /// `ErrorType::UnexpectedEOF` error. Off by default.
+    pub fn allow_comments(&mut self, allow: bool) {
+        self.allow_comments = allow;
+    }
+
+    /// Whether to accept strings, keys included, delimited by `'` rather
+    /// than `"`, as in JSON5. Inside them `"` needs no escape and `'` must
+    /// be escaped as `\'`, which is also accepted in double-quoted strings.
+    /// Off by default.
+    pub fn allow_single_quotes(&mut self, allow: bool) {
+        self.allow_single_quotes = allow;
+    }
+
+    /// The line and column of the next byte to be parsed, e.g. just after
+    /// the last value parsed. Lines and columns count from 1, as in errors.
+    pub fn position(&self) -> (usize, usize) {
+        (self.consumed_line, self.consumed_col.saturating_add(1))
+    }
+
+    /// The offset, counting from 0, of the next byte to be parsed
+    pub fn byte_offset(&self) -> usize {
+        if self.peek.is_some() { self.offset - 1 } else { self.offset }
+    }
+
+    /// An `ErrorType::Expected` error at the peeked byte `found`, reporting
+    /// the whole character there if it is not ASCII
+    fn expected_error(&mut self, expected: &'static str, found: u8) -> Error {
+        let (line, col, offset) = self.here();
+        let found = if found < 0x80 {
+            found as char
+        } else {
+            match self.parse_utf8_char() {
+                Ok(ch) => ch,
+                Err(e) => return e,
+            }
+        };
+        Error::new_at(line, col, offset, ErrorType::Expected { expected, found })
+    }
+
     fn error_at(&self, ty: ErrorType) -> Error {
-        Error {
-            line: self.line,
-            col: self.col,
-            error: ty,
-        }
+        let (line, col, offset) = self.here();
+        Error::new_at(line, col, offset, ty)
+    }
+
+    /// The line, column and byte offset of the byte being looked at, for
+    /// errors reported there after reading further
+    fn here(&self) -> (usize, usize, usize) {
+        (self.line, self.col, self.byte_offset())
     }
 
     fn peek(&mut self) -> Result<Option<u8>, Error> {
@@ -183,7 +682,7 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                 self.peek = Some(ch);
                 Ok(Some(ch))
             }
-            Some(Err(e)) => Err(self.error_at(ErrorType::Io(e))),
+            Some(Err(e)) => Err(self.error_at(source_error(e))),
             None => Ok(None),
         }
     }
@@ -196,7 +695,41 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
         }
     }
 
-    fn eat(&mut self) { self.peek = None; }
+    fn eat(&mut self) {
+        if let Some(ch) = self.peek.take() {
+            self.consumed_line = self.line;
+            self.consumed_col = self.col;
+            if let Some(ref mut raw) = self.raw {
+                raw.push(ch);
+            }
+        }
+    }
+
+    /// Enter a nested array or object, failing if this goes too deep
+    fn enter(&mut self) -> Result<(), Error> {
+        if self.depth >= self.max_depth {
+            return Err(self.error_at(ErrorType::DepthLimitExceeded));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Leave a nested array or object
+    fn leave(&mut self) { self.depth -= 1; }
+
+    /// Having just consumed a comma, consume `close` too if it follows and
+    /// trailing commas are allowed, returning whether it did
+    fn eat_trailing_comma_close(&mut self, close: u8) -> Result<bool, Error> {
+        if !self.allow_trailing_commas {
+            return Ok(false);
+        }
+        self.eat_whitespace()?;
+        if self.peek()? == Some(close) {
+            self.eat();
+            return Ok(true);
+        }
+        Ok(false)
+    }
 
     fn eat_whitespace(&mut self) -> Result<(), Error> {
         loop {
@@ -204,33 +737,138 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                 Some(b' ') | Some(b'\n') | Some(b'\r') => {
                     self.eat();
                 }
+                Some(c) if c >= 0x80 && self.allow_unicode_whitespace => {
+                    self.eat_unicode_whitespace()?;
+                }
+                Some(b'/') if self.allow_comments => {
+                    self.eat_comment()?;
+                }
                 _ => { return Ok(()); }
             }
         }
     }
 
-    fn eat_ident(&mut self, ident: &'static str) -> Result<(), Error> {
-        for c in ident.bytes() {
-            if self.peek()? == Some(c) {
+    /// Consume a comment, starting with the peeked `/`
+    fn eat_comment(&mut self) -> Result<(), Error> {
+        self.eat();
+        match self.peek_noeof()? {
+            b'/' => {
+                while let Some(c) = self.peek()? {
+                    self.eat();
+                    if c == b'\n' {
+                        break;
+                    }
+                }
+            }
+            b'*' => {
                 self.eat();
-            } else {
-                return Err(self.error_at(ErrorType::UnknownIdent));
+                let mut star = false;
+                loop {
+                    let c = self.peek_noeof()?;
+                    self.eat();
+                    if star && c == b'/' {
+                        break;
+                    }
+                    star = c == b'*';
+                }
             }
+            c => return Err(self.error_at(ErrorType::UnexpectedCharacter(c as char))),
         }
         Ok(())
     }
 
+    /// Consume a UTF-8 encoded space character starting with the peeked
+    /// byte. Any other non-ASCII character is an error here, since none can
+    /// start a token.
+    fn eat_unicode_whitespace(&mut self) -> Result<(), Error> {
+        let (line, col, offset) = self.here();
+        let ch = self.parse_utf8_char()?;
+        match ch {
+            '\u{a0}' | '\u{1680}' | '\u{2000}'..='\u{200a}' | '\u{2028}' | '\u{2029}' |
+            '\u{202f}' | '\u{205f}' | '\u{3000}' | '\u{feff}' => Ok(()),
+            _ => Err(Error::new_at(line, col, offset, ErrorType::UnexpectedCharacter(ch))),
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<Json, Error> {
+        const STANDARD: [(&str, JsonInner); 3] = [
+            ("null", JsonInner::Null),
+            ("true", JsonInner::Bool(true)),
+            ("false", JsonInner::Bool(false)),
+        ];
+
+        // Consume bytes for as long as they could still form a keyword
+        let mut word = String::new();
+        while let Some(c) = self.peek()? {
+            word.push(c as char);
+            if STANDARD.iter().any(|kw| kw.0.starts_with(&word[..])) ||
+               self.keywords.iter().any(|kw| kw.0.starts_with(&word[..])) {
+                self.eat();
+            } else {
+                word.pop();
+                break;
+            }
+        }
+
+        let ret = if let Some(kw) = STANDARD.iter().find(|kw| kw.0 == word) {
+            Json(kw.1.clone())
+        } else if let Some(kw) = self.keywords.iter().find(|kw| kw.0 == word) {
+            kw.1.clone()
+        } else {
+            return Err(self.error_at(ErrorType::UnknownIdent));
+        };
+        // A keyword must end at a token boundary, so `truer` is not `true`
+        // followed by garbage
+        match self.peek()? {
+            None | Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') |
+            Some(b',') | Some(b']') | Some(b'}') | Some(b':') => Ok(ret),
+            Some(c) if c >= 0x80 && self.allow_unicode_whitespace => Ok(ret),
+            Some(b'/') if self.allow_comments => Ok(ret),
+            Some(c) if c < 0x20 => Err(self.error_at(ErrorType::UnexpectedCharacter(c as char))),
+            Some(_) => Err(self.error_at(ErrorType::UnknownIdent)),
+        }
+    }
+
+    /// Consume an object key, which is a string unless scalar keys are
+    /// being coerced
+    fn parse_key(&mut self) -> Result<String, Error> {
+        if !self.coerce_scalar_keys {
+            return self.parse_string(self.max_key_len);
+        }
+        match self.peek_noeof()? {
+            b'-' | b'0'..=b'9' => self.parse_number(),
+            b'+' if self.allow_leading_plus => self.parse_number(),
+            b'a'..=b'z' | b'A'..=b'Z' => {
+                let (line, col, offset) = self.here();
+                let key = self.parse_ident()?;
+                match key.0 {
+                    JsonInner::Null | JsonInner::Bool(_) | JsonInner::Number(_) => Ok(key.to_string()),
+                    JsonInner::String(s) => Ok(s),
+                    _ => Err(Error::new_at(line, col, offset, ErrorType::ExpectedString)),
+                }
+            }
+            _ => self.parse_string(self.max_key_len),
+        }
+    }
+
     fn parse_number(&mut self) -> Result<String, Error> {
         #[derive(PartialEq)]
         enum State { Start, ZeroStart, PreDecimal, PostDecimal, InExp, PastExp }
 
-        let mut ret = String::new();
+        // Most numbers are short; start with enough room for any 64-bit integer
+        let mut ret = String::with_capacity(20);
         let mut state = State::Start;
+        let mut plus = false;
         while let Some(c) = self.peek()? {
             match c {
                 b'+' => {
                     if state == State::InExp {
                         state = State::PastExp;
+                    } else if state == State::Start && self.allow_leading_plus && !plus && ret.is_empty() {
+                        // Drop the sign, leaving a valid Json number
+                        plus = true;
+                        self.eat();
+                        continue;
                     } else {
                         return Err(self.error_at(ErrorType::UnexpectedCharacter('+')));
                     }
@@ -238,7 +876,7 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                 b'-' => {
                     if state == State::InExp {
                         state = State::PastExp;
-                    } else if state != State::Start {
+                    } else if state != State::Start || plus {
                         return Err(self.error_at(ErrorType::UnexpectedCharacter('-')));
                     }
                 }
@@ -264,6 +902,12 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                 b' ' | b'\r' | b'\n' | b'}' | b']' | b',' | b':' => {
                     break;
                 }
+                0x80..=0xff if self.allow_unicode_whitespace => {
+                    break;
+                }
+                b'/' if self.allow_comments => {
+                    break;
+                }
                 b'e' | b'E' => {
                     // e, E, e+, E+, e-, E- may appear at the end of a number. never at the start
                     if state == State::ZeroStart ||
@@ -278,6 +922,9 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                     return Err(self.error_at(ErrorType::UnexpectedCharacter(x as char)));
                 }
             }
+            if ret.len() >= self.max_number_len {
+                return Err(self.error_at(ErrorType::NumberTooLong));
+            }
             ret.push(c as char);
             self.eat();
         }
@@ -288,16 +935,74 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
         }
     }
 
-    /// Consume a string, assuming the first character has been vetted to be '"'.
-    fn parse_string(&mut self) -> Result<String, Error> {
+    /// Consume a string, reporting end-of-file within it at the opening
+    /// quote. Strings longer than `max_len` are object keys over the limit.
+    fn parse_string(&mut self, max_len: usize) -> Result<String, Error> {
+        let quoted = match self.peek()? {
+            Some(b'"') => true,
+            Some(b'\'') => self.allow_single_quotes,
+            _ => false,
+        };
+        let (line, col, offset) = self.here();
+        self.parse_string_inner(max_len).map_err(|e| match e.error {
+            ErrorType::UnexpectedEOF if quoted => {
+                Error::new_at(line, col, offset, ErrorType::UnterminatedString(line, col))
+            }
+            _ => e,
+        })
+    }
+
+    /// Decode a multibyte UTF-8 character, starting with the peeked byte.
+    /// Overlong encodings, surrogates and code points past U+10FFFF are
+    /// rejected at the first byte which makes them so.
+    fn parse_utf8_char(&mut self) -> Result<char, Error> {
+        let lead = self.peek_noeof()?;
+        let len = match lead {
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return Err(self.error_at(ErrorType::InvalidUtf8)),
+        };
+        self.eat();
+        let mut buf = [lead, 0, 0, 0];
+        for (n, byte) in buf.iter_mut().enumerate().take(len).skip(1) {
+            let range = match (n, lead) {
+                (1, 0xe0) => 0xa0..=0xbf,
+                (1, 0xed) => 0x80..=0x9f,
+                (1, 0xf0) => 0x90..=0xbf,
+                (1, 0xf4) => 0x80..=0x8f,
+                _ => 0x80..=0xbf,
+            };
+            // Running out of input is end-of-file, not a bad sequence
+            let b = self.peek_noeof()?;
+            if !range.contains(&b) {
+                return Err(self.error_at(ErrorType::InvalidUtf8));
+            }
+            *byte = b;
+            self.eat();
+        }
+        let s = str::from_utf8(&buf[..len]).expect("UTF-8 sequence was validated");
+        Ok(s.chars().next().expect("sequence is not empty"))
+    }
+
+    /// Consume a string, assuming the first character has been vetted to be
+    /// a quote
+    fn parse_string_inner(&mut self, max_len: usize) -> Result<String, Error> {
         #[derive(PartialEq)]
         enum State { Start, Scanning, Escaping, Done }
 
         let mut ret = String::new();
         let mut state = State::Start;
+        let mut quote = b'"';
         while let Some(mut c) = self.peek()? {
             match c {
-                b'"' => {
+                b'\'' if state == State::Start && self.allow_single_quotes => {
+                    quote = c;
+                    state = State::Scanning;
+                    self.eat();
+                    continue;
+                }
+                _ if c == quote => {
                     match state {
                         State::Start => { state = State::Scanning; self.eat(); continue; }
                         State::Scanning => { self.eat(); state = State::Done; break; }
@@ -318,17 +1023,31 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                         State::Start => {
                             return Err(self.error_at(ErrorType::ExpectedString));
                         }
+                        // Control characters, NUL included, must be escaped
+                        State::Scanning if c < 0x20 && self.reject_control_characters => {
+                            return Err(self.error_at(ErrorType::UnexpectedCharacter(c as char)));
+                        }
+                        State::Scanning if c >= 0x80 => {
+                            ret.push(self.parse_utf8_char()?);
+                            if ret.len() > max_len {
+                                return Err(self.error_at(ErrorType::KeyTooLong));
+                            }
+                            continue;
+                        }
                         State::Scanning => {
                             // Do nothing -- after the match we will push this character onto the buffer
                         }
                         State::Escaping => {
                             c = match c {
-                                b'b' => 7,
+                                b'b' => 8,
                                 b'f' => 12,
                                 b'n' => b'\n',
                                 b'r' => b'\r',
                                 b't' => b'\t',
                                 b'/' => b'/',
+                                // Only reached when the other quote delimits the string
+                                b'"' => b'"',
+                                b'\'' if self.allow_single_quotes => b'\'',
                                 b'\\' => unreachable!(),  // covered above in the main b'\\' branch
                                 b'u' => {
                                     // Read as many \uXXXX's in a row as we can, then parse them all as
@@ -362,6 +1081,9 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                                             Err(_) => return Err(self.error_at(ErrorType::UnpairedSurrogate))
                                         }
                                     }
+                                    if ret.len() > max_len {
+                                        return Err(self.error_at(ErrorType::KeyTooLong));
+                                    }
                                     continue;
                                 }
                                 _ => { return Err(self.error_at(ErrorType::MalformedEscape)); }
@@ -373,6 +1095,9 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
                 }
             }
             ret.push(c as char);
+            if ret.len() > max_len {
+                return Err(self.error_at(ErrorType::KeyTooLong));
+            }
             self.eat();
         }
         if state == State::Done {
@@ -384,111 +1109,469 @@ impl<I: Iterator<Item=io::Result<u8>>> Parser<I> {
 
     /// Consume the internal iterator and produce a Json object
     pub fn parse(&mut self) -> Result<Json, super::Error> {
+        self.strip_prefix()?;
+        Ok(self.parse_value()?)
+    }
+
+    /// Parse as with `parse`, but fail with `ErrorType::UnexpectedType`,
+    /// positioned at the start of the value, unless its type is one of
+    /// `allowed`, e.g. to accept only an object or an array as the body of
+    /// a request
+    pub fn parse_expecting(&mut self, allowed: &[JsonType]) -> Result<Json, super::Error> {
+        self.strip_prefix()?;
         self.eat_whitespace()?;
+        self.peek()?;
+        let (line, col, offset) = self.here();
+        let ret = self.parse_value()?;
+        if allowed.contains(&ret.kind()) {
+            Ok(ret)
+        } else {
+            let error = ErrorType::UnexpectedType { allowed: allowed.to_vec(), found: ret.kind() };
+            Err(From::from(Error::new_at(line, col, offset, error)))
+        }
+    }
 
-        let first_ch = match self.peek() {
-            Ok(Some(c)) => c,
-            Ok(None) => return Err(From::from(self.error_at(ErrorType::UnexpectedEOF))),
-            Err(e) => return Err(From::from(e)),
-        };
+    /// Parse an object, passing each member to `f` as soon as it has been
+    /// parsed rather than collecting them, so only one member's value at a
+    /// time is held in memory. Members are passed in order, including any
+    /// with duplicate keys unless `reject_duplicate_keys` is set, in which
+    /// case every key seen so far is also kept, to check against. If `f`
+    /// returns an error, parsing stops there and the error is returned.
+    /// Anything but an object is an `ErrorType::Expected` error.
+    pub fn parse_object_streaming<F>(&mut self, mut f: F) -> Result<(), super::Error>
+        where F: FnMut(String, Json) -> Result<(), super::Error>
+    {
+        self.strip_prefix()?;
+        self.eat_whitespace()?;
+        match self.peek_noeof()? {
+            b'{' => self.parse_members(None, &mut f),
+            x => Err(From::from(self.expected_error("`{`", x))),
+        }
+    }
 
-        match first_ch {
-            // keywords
-            b'n' => {
-                self.eat_ident("null")?;
-                Ok(Json(JsonInner::Null))
-            }
-            b't' => {
-                self.eat_ident("true")?;
-                Ok(Json(JsonInner::Bool(true)))
-            }
-            b'f' => {
-                self.eat_ident("false")?;
-                Ok(Json(JsonInner::Bool(false)))
-            }
-            // numbers
-            b'-' | b'0' ... b'9' => {
-                Ok(Json(JsonInner::Number(self.parse_number()?)))
+    /// Skip the anti-XSSI prefix, if that is enabled and it is there
+    fn strip_prefix(&mut self) -> Result<(), Error> {
+        // Nothing has been read iff we are still at the very start
+        if self.strip_xssi_prefix && self.line == 1 && self.col == 0 && self.peek()? == Some(b')') {
+            for c in b")]}'".iter().cloned() {
+                let ch = self.peek_noeof()?;
+                if ch != c {
+                    return Err(self.error_at(ErrorType::UnexpectedCharacter(ch as char)));
+                }
+                self.eat();
             }
-            // strings
-            b'"' | b'\'' => {
-                Ok(Json(JsonInner::String(self.parse_string()?)))
+        }
+        Ok(())
+    }
+
+    /// Parse exactly one value, skipping whitespace before it but reading
+    /// nothing after it, so the rest of the input can be handled some other
+    /// way, e.g. a binary body following a Json header. This is the
+    /// primitive for streaming: call it repeatedly to read a sequence of
+    /// values, or use `into_inner` to take back the input.
+    ///
+    /// Arrays, objects and strings end at their closing byte. Numbers and
+    /// keywords can only be seen to end by reading one byte past them,
+    /// which is kept unconsumed and returned by `into_inner`.
+    pub fn parse_one(&mut self) -> Result<Json, super::Error> {
+        Ok(self.parse_value()?)
+    }
+
+    /// Check that the input is a sequence of whitespace-separated values,
+    /// such as newline-delimited Json, returning an error for each one that
+    /// is malformed. After an error, the rest of its line is skipped and
+    /// checking resumes on the next line. This suits input with one value
+    /// per line; when a malformed value spans several lines, its remaining
+    /// lines are checked as values of their own and will usually produce
+    /// follow-on errors. The values are parsed and discarded one at a time,
+    /// so the whole input is never held in memory. An error from the input
+    /// itself ends the check.
+    pub fn validate_stream(&mut self) -> Vec<super::Error> {
+        let mut ret = vec![];
+        loop {
+            match self.eat_whitespace().and_then(|_| self.peek()) {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(e) => { ret.push(From::from(e)); break; }
             }
-            // arrays
-            b'[' => {
-                self.eat();
-                let mut ret = vec![];
-                loop {
-                    self.eat_whitespace()?;
-                    if !(ret.is_empty() && self.peek_noeof()? == b']') {
-                        ret.push(self.parse()?);
-                        self.eat_whitespace()?;
-                    }
-                    match self.peek_noeof()? {
-                        b',' => { self.eat(); }
-                        b']' => { self.eat(); break; }
-                        _ => { return Err(From::from(self.error_at(ErrorType::UnknownIdent))); }
-                    }
+            if let Err(e) = self.parse_value() {
+                let fatal = matches!(*e.error.innermost(), ErrorType::Io(_) | ErrorType::Source(_));
+                ret.push(From::from(e));
+                if fatal || self.skip_line().is_err() {
+                    break;
                 }
-                Ok(Json(JsonInner::Array(ret)))
             }
-            // objects TODO
-            b'{' => {
-                self.eat();
-                let mut ret = vec![];
-                loop {
-                    self.eat_whitespace()?;
-                    // special-case {}
-                    if ret.is_empty() && self.peek_noeof()? == b'}' {
-                        self.eat();
-                        break;
-                    }
-                    // parse key
-                    let key = self.parse_string()?;
-                    self.eat_whitespace()?;
-                    // parse : separator
-                    let sep_ch = self.peek_noeof()?;
-                    if sep_ch == b':' {
-                        self.eat();
-                        self.eat_whitespace()?;
-                    } else {
-                        return Err(From::from(self.error_at(ErrorType::UnexpectedCharacter(sep_ch as char))));
-                    }
-                    // parse value
-                    let val = self.parse()?;
-                    ret.push((key, val));
-                    self.eat_whitespace()?;
-                    // parse , separator
-                    match self.peek_noeof()? {
-                        b',' => { self.eat(); },
-                        b'}' /* { */ => { self.eat(); break; }
-                        x => { return Err(From::from(self.error_at(ErrorType::UnexpectedCharacter(x as char)))); }
-                    }
-                }
-                Ok(Json(JsonInner::Object(ret)))
+        }
+        ret
+    }
+
+    /// Skip past the next newline, or to the end of the input
+    fn skip_line(&mut self) -> Result<(), Error> {
+        while let Some(ch) = self.peek()? {
+            self.eat();
+            if ch == b'\n' {
+                break;
             }
-            _ => Err(From::from(self.error_at(ErrorType::UnknownIdent)))
         }
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use {Json, JsonInner};
-    use {Error, ErrorInner};
+    /// Consume the parser, returning the byte which was read ahead but not
+    /// parsed, if any, and the input iterator, positioned after that byte
+    pub fn into_inner(self) -> (Option<u8>, I) {
+        (self.peek, self.iter)
+    }
 
-    macro_rules! jnull( () => (Json(JsonInner::Null)) );
-    macro_rules! jbool( ($e:expr) => (Json(JsonInner::Bool($e))) );
-    macro_rules! jnum( ($e:expr) => (Json(JsonInner::Number($e.to_owned()))) );
-    macro_rules! jstr( ($e:expr) => (Json(JsonInner::String($e.to_owned()))) );
-    macro_rules! jarr( ($($e:expr),*) => (Json(JsonInner::Array(vec![$($e),*]))) );
-    macro_rules! jobj( ($($k:expr => $v:expr),*) => ({
-        let mut vec = vec![];
-        &mut vec;  /* dummy "use as mut" to avoid errors in case of no inserts */
-        $(
-            vec.push(($k.to_owned(), $v));
-        )*
-        Json(JsonInner::Object(vec))
+    /// Consume the internal iterator and produce a Json object, along with
+    /// the byte span `(start, end)` of each node in the input. The end is
+    /// exclusive. Nodes are identified by their index in a pre-order walk
+    /// of the tree: each node comes before its array elements or object
+    /// values, which come in order. Object keys are not nodes.
+    pub fn parse_with_spans(&mut self) -> Result<(Json, Vec<(usize, usize)>), super::Error> {
+        self.spans = Some(vec![]);
+        let res = self.parse();
+        let spans = self.spans.take().unwrap_or_default();
+        res.map(|json| (json, spans))
+    }
+
+    /// Consume the internal iterator and produce a Json object, along with
+    /// the source text of every node for whose path `capture` returns true,
+    /// e.g. to pass part of a document through without any normalization.
+    /// Nodes inside a captured node are not captured separately. Captures
+    /// are returned in the order they appear in the input; any text which
+    /// is not valid UTF-8 is converted lossily.
+    pub fn parse_with_raw<F>(&mut self, capture: F) -> Result<(Json, Vec<RawJson>), super::Error>
+        where F: Fn(&[PathSegment]) -> bool
+    {
+        self.path.clear();
+        self.raw = None;
+        let res = match self.strip_prefix() {
+            Ok(()) => self.parse_value_with(Some(&capture)).map_err(From::from),
+            Err(e) => Err(From::from(e)),
+        };
+        self.raw = None;
+        let captured = std::mem::take(&mut self.captured);
+        res.map(|json| (json, captured))
+    }
+
+    fn parse_value(&mut self) -> Result<Json, Error> {
+        self.parse_value_with(None)
+    }
+
+    /// Parse a value, capturing the text of nodes selected by `capture`.
+    /// The predicate is passed down rather than stored, so that the parser
+    /// stays `Send` and `Sync` whatever it is.
+    fn parse_value_with(&mut self, capture: Option<CapturePredicate>) -> Result<Json, Error> {
+        self.eat_whitespace()?;
+        self.peek_noeof()?;
+
+        let capturing = self.raw.is_none() && capture.is_some_and(|capture| capture(&self.path));
+        if capturing {
+            self.raw = Some(vec![]);
+        }
+
+        let start = self.byte_offset();
+        let node_id = match self.spans {
+            Some(ref mut spans) => {
+                spans.push((start, start));
+                spans.len() - 1
+            }
+            None => 0,
+        };
+        let ret = self.parse_value_inner(capture)?;
+        let end = self.byte_offset();
+        if let Some(ref mut spans) = self.spans {
+            spans[node_id].1 = end;
+        }
+        if capturing {
+            let text = self.raw.take().unwrap_or_default();
+            self.captured.push(RawJson {
+                path: self.path.clone(),
+                text: String::from_utf8_lossy(&text).into_owned(),
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Consume an object, assuming the next byte is its `{`, passing each
+    /// member to `f` as soon as it is parsed
+    fn parse_members<R, F>(&mut self, capture: Option<CapturePredicate>, f: &mut F) -> Result<(), R>
+        where R: From<Error>,
+              F: FnMut(String, Json) -> Result<(), R>
+    {
+        self.enter()?;
+        let res = self.parse_members_inner(capture, f);
+        self.leave();
+        res
+    }
+
+    fn parse_members_inner<R, F>(&mut self, capture: Option<CapturePredicate>, f: &mut F) -> Result<(), R>
+        where R: From<Error>,
+              F: FnMut(String, Json) -> Result<(), R>
+    {
+        self.eat();
+        let mut first = true;
+        let mut seen = HashSet::new();
+        loop {
+            self.eat_whitespace()?;
+            // special-case {}
+            if first && self.peek_noeof()? == b'}' {
+                self.eat();
+                break;
+            }
+            first = false;
+            // parse key
+            let (line, col, offset) = self.here();
+            let key = self.parse_key()?;
+            if self.reject_duplicate_keys && !seen.insert(key.clone()) {
+                return Err(From::from(Error::new_at(line, col, offset, ErrorType::DuplicateKey(key))));
+            }
+            self.eat_whitespace()?;
+            // parse : separator
+            let sep_ch = self.peek_noeof()?;
+            if sep_ch == b':' {
+                self.eat();
+                self.eat_whitespace()?;
+            } else {
+                return Err(From::from(self.error_at(ErrorType::ExpectedColon)));
+            }
+            // parse value
+            let tracking = capture.is_some();
+            if tracking {
+                self.path.push(PathSegment::Key(key.clone()));
+            }
+            let val = match self.parse_value_with(capture) {
+                Ok(val) => val,
+                Err(e) => {
+                    return Err(From::from(e.in_context(|inner| ErrorType::InObjectValue(key, Box::new(inner)))));
+                }
+            };
+            if tracking {
+                self.path.pop();
+            }
+            f(key, val)?;
+            self.eat_whitespace()?;
+            // parse , separator
+            match self.peek_noeof()? {
+                b',' => {
+                    self.eat();
+                    if self.eat_trailing_comma_close(b'}')? {
+                        break;
+                    }
+                }
+                b'}' /* { */ => { self.eat(); break; }
+                x @ 0x00..=0x1f => { return Err(From::from(self.error_at(ErrorType::UnexpectedCharacter(x as char)))); }
+                x => { return Err(From::from(self.expected_error("`,` or `}`", x))); }
+            }
+        }
+        Ok(())
+    }
+
+    /// Consume an array, assuming the next byte is its `[`
+    fn parse_elements(&mut self, capture: Option<CapturePredicate>) -> Result<Vec<Json>, Error> {
+        self.eat();
+        let mut ret = vec![];
+        loop {
+            self.eat_whitespace()?;
+            if !(ret.is_empty() && self.peek_noeof()? == b']') {
+                let idx = ret.len();
+                let tracking = capture.is_some();
+                if tracking {
+                    self.path.push(PathSegment::Index(idx));
+                }
+                match self.parse_value_with(capture) {
+                    Ok(val) => ret.push(val),
+                    Err(e) => {
+                        return Err(e.in_context(|inner| ErrorType::InArrayElement(idx, Box::new(inner))));
+                    }
+                }
+                if tracking {
+                    self.path.pop();
+                }
+                self.eat_whitespace()?;
+            }
+            match self.peek_noeof()? {
+                b',' => {
+                    self.eat();
+                    if self.eat_trailing_comma_close(b']')? {
+                        break;
+                    }
+                }
+                b']' => { self.eat(); break; }
+                x @ 0x00..=0x1f => { return Err(self.error_at(ErrorType::UnexpectedCharacter(x as char))); }
+                x => { return Err(self.expected_error("`,` or `]`", x)); }
+            }
+        }
+        Ok(ret)
+    }
+
+    fn parse_value_inner(&mut self, capture: Option<CapturePredicate>) -> Result<Json, Error> {
+        let first_ch = self.peek_noeof()?;
+
+        match first_ch {
+            // keywords
+            b'a'..=b'z' | b'A'..=b'Z' => {
+                self.parse_ident()
+            }
+            // numbers
+            b'-' | b'0' ... b'9' | b'+' if first_ch != b'+' || self.allow_leading_plus => {
+                let (line, col, offset) = self.here();
+                let num = self.parse_number()?;
+                if self.unsafe_integers == UnsafeIntegers::Reject && !is_safe_integer(&num) {
+                    return Err(Error::new_at(line, col, offset, ErrorType::UnsafeInteger));
+                }
+                Ok(Json(JsonInner::Number(num)))
+            }
+            // strings
+            b'"' => {
+                Ok(Json(JsonInner::String(self.parse_string(usize::MAX)?)))
+            }
+            b'\'' if self.allow_single_quotes => {
+                Ok(Json(JsonInner::String(self.parse_string(usize::MAX)?)))
+            }
+            // arrays
+            b'[' => {
+                self.enter()?;
+                let res = self.parse_elements(capture);
+                self.leave();
+                Ok(Json(JsonInner::Array(res?)))
+            }
+            // objects TODO
+            b'{' => {
+                let mut ret = vec![];
+                self.parse_members(capture, &mut |key, val| {
+                    ret.push((key, val));
+                    Ok::<(), Error>(())
+                })?;
+                Ok(Json(JsonInner::Object(ret)))
+            }
+            // Control characters, NUL included, cannot start any token
+            0x00..=0x1f => Err(self.error_at(ErrorType::UnexpectedCharacter(first_ch as char))),
+            _ => Err(self.error_at(ErrorType::UnknownIdent))
+        }
+    }
+}
+
+/// An iterator over the elements of a top-level Json array, which parses
+/// one element at a time rather than the whole array at once
+pub struct ArrayElements<I: Iterator> {
+    parser: Parser<I>,
+    started: bool,
+    done: bool,
+}
+
+impl<I: Iterator<Item=io::Result<u8>>> ArrayElements<I> {
+    /// Construct a new iterator over the array in the given input
+    pub fn new(iter: I) -> ArrayElements<I> {
+        ArrayElements::from_parser(Parser::new(iter))
+    }
+}
+
+impl<I, E> ArrayElements<I>
+    where I: Iterator<Item=Result<u8, E>>,
+          E: error::Error + Send + Sync + 'static
+{
+    /// Construct a new iterator over the array read by an existing parser,
+    /// which may have been configured with non-default options
+    pub fn from_parser(parser: Parser<I>) -> ArrayElements<I> {
+        ArrayElements {
+            parser,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Consume up to the start of the next element, returning false if
+    /// the array ended instead
+    fn advance(&mut self) -> Result<bool, Error> {
+        self.parser.eat_whitespace()?;
+        if !self.started {
+            self.started = true;
+            match self.parser.peek_noeof()? {
+                b'[' => {
+                    self.parser.enter()?;
+                    self.parser.eat();
+                }
+                x => return Err(self.parser.expected_error("`[`", x)),
+            }
+            self.parser.eat_whitespace()?;
+            if self.parser.peek_noeof()? == b']' {
+                self.parser.eat();
+                return Ok(false);
+            }
+        } else {
+            match self.parser.peek_noeof()? {
+                b',' => {
+                    self.parser.eat();
+                    if self.parser.eat_trailing_comma_close(b']')? {
+                        return Ok(false);
+                    }
+                }
+                b']' => { self.parser.eat(); return Ok(false); }
+                x => return Err(self.parser.expected_error("`,` or `]`", x)),
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<I, E> Iterator for ArrayElements<I>
+    where I: Iterator<Item=Result<u8, E>>,
+          E: error::Error + Send + Sync + 'static
+{
+    type Item = Result<Json, super::Error>;
+
+    fn next(&mut self) -> Option<Result<Json, super::Error>> {
+        if self.done {
+            return None;
+        }
+        let res = match self.advance() {
+            Ok(true) => self.parser.parse_value(),
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => Err(e),
+        };
+        if res.is_err() {
+            self.done = true;
+        }
+        Some(res.map_err(From::from))
+    }
+}
+
+/// Whether a number is not an integer literal, or is one which fits in an
+/// IEEE double without loss
+fn is_safe_integer(num: &str) -> bool {
+    const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+    if num.contains(['.', 'e', 'E']) {
+        return true;
+    }
+    let digits = num.trim_start_matches('-');
+    // Avoid overflowing the parse; MAX_SAFE_INTEGER has 16 digits
+    digits.len() <= 16 && digits.parse::<u64>().map(|n| n <= MAX_SAFE_INTEGER).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use {Json, JsonInner};
+    use parser::ErrorType;
+    use {Error, ErrorInner};
+
+    macro_rules! jnull( () => (Json(JsonInner::Null)) );
+
+    macro_rules! jbool( ($e:expr) => (Json(JsonInner::Bool($e))) );
+    macro_rules! jnum( ($e:expr) => (Json(JsonInner::Number($e.to_owned()))) );
+    macro_rules! jstr( ($e:expr) => (Json(JsonInner::String($e.to_owned()))) );
+    macro_rules! jarr( ($($e:expr),*) => (Json(JsonInner::Array(vec![$($e),*]))) );
+    macro_rules! jobj( ($($k:expr => $v:expr),*) => ({
+        let mut vec = vec![];
+        &mut vec;  /* dummy "use as mut" to avoid errors in case of no inserts */
+        $(
+            vec.push(($k.to_owned(), $v));
+        )*
+        Json(JsonInner::Object(vec))
     }) );
 
     #[test]
@@ -497,7 +1580,7 @@ mod tests {
         assert_eq!(Json::from_str("  true  ").unwrap(), jbool!(true));
         assert_eq!(Json::from_str(" false ").unwrap(), jbool!(false));
 
-        assert_eq!(Json::from_str("\"\\n\\r\\t\\b\\f \\\\ \\/\"").unwrap(), jstr!("\n\r\t\u{7}\u{c} \\ /"));
+        assert_eq!(Json::from_str("\"\\n\\r\\t\\b\\f \\\\ \\/\"").unwrap(), jstr!("\n\r\t\u{8}\u{c} \\ /"));
         assert_eq!(Json::from_str("\"\\\"\"").unwrap(), jstr!("\""));
         assert_eq!(Json::from_str(" \"string\"").unwrap(), jstr!("string"));
         assert_eq!(Json::from_str("\"i've \\\"ed this\"").unwrap(), jstr!("i've \"ed this"));
@@ -601,6 +1684,1217 @@ mod tests {
         assert!(Json::from_str("{\"key1\": \"val\", \"key2\":\"val\",}").is_err());
     }
 
+    #[test]
+    fn test_depth_limit() {
+        use parser::DEFAULT_MAX_DEPTH;
+
+        let nested = |n: usize| {
+            let mut s = String::new();
+            for _ in 0..n { s.push('['); }
+            for _ in 0..n { s.push(']'); }
+            s
+        };
+        assert!(Json::from_str(&nested(DEFAULT_MAX_DEPTH)).is_ok());
+        match Json::from_str(&nested(DEFAULT_MAX_DEPTH + 1)) {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.col, DEFAULT_MAX_DEPTH + 1);
+                // Only the innermost levels are spelled out
+                let path = "/0".repeat(DEFAULT_MAX_DEPTH - 3);
+                assert_eq!(e.to_string(), format!("1:129: error parsing value at {}: error parsing array element 0: \
+                                                   error parsing array element 0: error parsing array element 0: \
+                                                   nesting depth limit exceeded", path));
+                // The full context is still there, though
+                match *e.kind() {
+                    ErrorType::InArrayElement(0, _) => {}
+                    _ => panic!("wrong error type"),
+                }
+                match *e.kind().innermost() {
+                    ErrorType::DepthLimitExceeded => {}
+                    _ => panic!("wrong innermost error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+        assert!(Json::from_str("{\"a\": [{\"b\": [[[]]]}]}").is_ok());
+
+        match Json::from_str("{\"a/b\": [{\"~\": {\"c\": [1, x]}}]}") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.to_string(), "1:26: error parsing value at /a~1b/0: error parsing value for key `~`: \
+                                           error parsing value for key `c`: error parsing array element 1: unknown ident");
+            }
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn test_set_max_depth() {
+        use parser::{Parser, DEFAULT_MAX_DEPTH};
+
+        let parse = |s: &str, depth: usize| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.set_max_depth(depth);
+            parser.parse()
+        };
+        assert!(parse("{\"a\": [1]}", 2).is_ok());
+        match parse("{\"a\": [[1]]}", 2) {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.col, 8);
+                match *e.error.innermost() {
+                    ErrorType::DepthLimitExceeded => {},
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+        // Scalars need no nesting at all
+        assert!(parse("1", 0).is_ok());
+        assert!(parse("[]", 0).is_err());
+
+        // A hostile input fails cleanly instead of overflowing the stack
+        let deep = "[".repeat(1_000_000);
+        assert!(parse(&deep, DEFAULT_MAX_DEPTH).is_err());
+        let nested = format!("{}{}", "[".repeat(200), "]".repeat(200));
+        assert!(parse(&nested, DEFAULT_MAX_DEPTH).is_err());
+        assert!(parse(&nested, 200).is_ok());
+    }
+
+    #[test]
+    fn test_xssi_prefix() {
+        use parser::Parser;
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.strip_xssi_prefix(true);
+            parser.parse()
+        };
+        assert_eq!(parse(")]}'\n{\"k\": 1}").unwrap(), jobj!["k" => jnum!("1")]);
+        assert_eq!(parse(")]}'[1]").unwrap(), jarr![jnum!("1")]);
+        assert_eq!(parse("[1]").unwrap(), jarr![jnum!("1")]);
+        assert!(Json::from_str(")]}'\n{\"k\": 1}").is_err());
+
+        // Only at the very start
+        assert!(parse(" )]}'\n[1]").is_err());
+        assert!(parse("[1, )]}'\n2]").is_err());
+        assert!(parse(")]}'\n)]}'\n[1]").is_err());
+        assert!(parse(")]}[1]").is_err());
+        assert!(parse(")]}'").is_err());
+    }
+
+    #[test]
+    fn test_spans() {
+        use parser::Parser;
+
+        let input = "{\"a\": [1, \"xy\"],\n \"b\": true, \"c\": -5.5}  ";
+        let (json, spans) = Parser::new(input.bytes().map(Ok)).parse_with_spans().unwrap();
+        assert_eq!(json, Json::from_str(input).unwrap());
+        assert_eq!(spans.len(), 6);
+        let text: Vec<&str> = spans.iter().map(|&(start, end)| &input[start..end]).collect();
+        assert_eq!(text, vec![
+            "{\"a\": [1, \"xy\"],\n \"b\": true, \"c\": -5.5}",
+            "[1, \"xy\"]",
+            "1",
+            "\"xy\"",
+            "true",
+            "-5.5",
+        ]);
+        assert_eq!(spans[3], (10, 14));
+
+        let (_, spans) = Parser::new("  [[], {}]".bytes().map(Ok)).parse_with_spans().unwrap();
+        assert_eq!(spans, vec![(2, 10), (3, 5), (7, 9)]);
+
+        assert!(Parser::new("[1, 2".bytes().map(Ok)).parse_with_spans().is_err());
+    }
+
+    #[test]
+    fn test_raw_capture() {
+        use parser::{Parser, RawJson};
+        use PathSegment;
+
+        let input = "{\"id\": 1, \"payload\": {  \"b\" :[1.50,\n 2e0 ],\"a\":\"\\u0041\" },\n \"list\": [ 3 , {\"payload\": 4} ]}";
+        let (json, raw) = Parser::new(input.bytes().map(Ok)).parse_with_raw(|path| {
+            match path.last() {
+                Some(PathSegment::Key(key)) => key == "payload",
+                Some(PathSegment::Index(idx)) => *idx == 0,
+                None => false,
+            }
+        }).unwrap();
+        assert_eq!(json, Json::from_str(input).unwrap());
+        assert_eq!(raw, vec![
+            RawJson {
+                path: vec![PathSegment::Key("payload".to_owned())],
+                text: "{  \"b\" :[1.50,\n 2e0 ],\"a\":\"\\u0041\" }".to_owned(),
+            },
+            RawJson {
+                path: vec![PathSegment::Key("list".to_owned()), PathSegment::Index(0)],
+                text: "3".to_owned(),
+            },
+            RawJson {
+                path: vec![PathSegment::Key("list".to_owned()), PathSegment::Index(1), PathSegment::Key("payload".to_owned())],
+                text: "4".to_owned(),
+            },
+        ]);
+        // The captured text is an exact slice of the input
+        assert!(input.contains(&raw[0].text[..]));
+
+        // Capturing the root captures the whole document, minus surrounding whitespace
+        let (_, raw) = Parser::new(" [ 1,2 ] ".bytes().map(Ok)).parse_with_raw(|path| path.is_empty()).unwrap();
+        assert_eq!(raw.len(), 1);
+        assert_eq!(raw[0].text, "[ 1,2 ]");
+
+        assert!(Parser::new("{\"a\": [1".bytes().map(Ok)).parse_with_raw(|_| true).is_err());
+
+        // The predicate may borrow, and the parser is reusable afterwards
+        let wanted = vec![PathSegment::Index(1)];
+        let mut parser = Parser::new("[1, [2], 3] [4]".bytes().map(Ok));
+        let (_, raw) = parser.parse_with_raw(|path| path == &wanted[..]).unwrap();
+        assert_eq!(raw, vec![RawJson { path: wanted.clone(), text: "[2]".to_owned() }]);
+        assert_eq!(parser.parse().unwrap(), jarr![jnum!("4")]);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        use std::{io, vec};
+        use parser::{ArrayElements, Parser};
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Parser<vec::IntoIter<io::Result<u8>>>>();
+        assert_send_sync::<ArrayElements<vec::IntoIter<io::Result<u8>>>>();
+    }
+
+    #[test]
+    fn test_unsafe_integers() {
+        use parser::{Parser, ErrorType, UnsafeIntegers};
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.warn_or_reject_unsafe_integers(UnsafeIntegers::Reject);
+            parser.parse()
+        };
+        assert_eq!(parse("9007199254740991").unwrap(), jnum!("9007199254740991"));
+        assert_eq!(parse("-9007199254740991").unwrap(), jnum!("-9007199254740991"));
+        assert_eq!(parse("[0, -0, 12]").unwrap(), jarr![jnum!("0"), jnum!("-0"), jnum!("12")]);
+        // Not integer literals
+        assert_eq!(parse("9007199254740993.0").unwrap(), jnum!("9007199254740993.0"));
+        assert_eq!(parse("1e300").unwrap(), jnum!("1e300"));
+
+        match parse("[1, -9007199254740992]") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!((e.line, e.col), (1, 5));
+                match *e.error.innermost() {
+                    ErrorType::UnsafeInteger => {},
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+        assert!(parse("9007199254740992").is_err());
+        assert!(parse("123456789012345678901234567890").is_err());
+
+        // Allowed by default
+        assert_eq!(Json::from_str("9007199254740992").unwrap(), jnum!("9007199254740992"));
+        assert_eq!(Json::from_str("123456789012345678901234567890").unwrap(),
+                   jnum!("123456789012345678901234567890"));
+    }
+
+    #[test]
+    fn test_array_elements() {
+        use std::io::Cursor;
+        use parser::ErrorType;
+
+        let mut input = String::from("[");
+        for i in 0..10_000 {
+            if i > 0 { input.push_str(",\n"); }
+            input.push_str(&i.to_string());
+        }
+        input.push_str(" ]");
+        let mut count = 0;
+        for (i, elem) in Json::array_elements_from_reader(Cursor::new(input.as_bytes())).enumerate() {
+            assert_eq!(elem.unwrap(), jnum!(i.to_string()));
+            count += 1;
+        }
+        assert_eq!(count, 10_000);
+
+        let input = " [ [1, [2]], {\"k\": [\"]\", \",\"]}, \"x\" , null]  ";
+        let elems: Vec<Json> = Json::array_elements_from_reader(input.as_bytes())
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(Json::from(elems), Json::from_str(input).unwrap());
+
+        assert_eq!(Json::array_elements_from_reader(&b"[]"[..]).count(), 0);
+        assert_eq!(Json::array_elements_from_reader(&b"[ ]"[..]).count(), 0);
+
+        let mut iter = Json::array_elements_from_reader(&b"[1, {\"a\": 2}, 3 4, 5]"[..]);
+        assert_eq!(iter.next().unwrap().unwrap(), jnum!("1"));
+        assert_eq!(iter.next().unwrap().unwrap(), jobj!["a" => jnum!("2")]);
+        assert_eq!(iter.next().unwrap().unwrap(), jnum!("3"));
+        match iter.next() {
+            Some(Err(Error(ErrorInner::Parser(e)))) => {
+                assert_eq!((e.line, e.col), (1, 17));
+                match e.error {
+                    ErrorType::Expected { expected: "`,` or `]`", found: '4' } => {},
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("expected an error"),
+        }
+        assert!(iter.next().is_none());
+
+        let mut iter = Json::array_elements_from_reader(&b"{\"a\": 1}"[..]);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        let mut iter = Json::array_elements_from_reader(&b"[1, "[..]);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(Json::array_elements_from_reader(&b"[1,]"[..]).any(|r| r.is_err()));
+    }
+
+    #[test]
+    fn test_position() {
+        use parser::Parser;
+
+        let mut parser = Parser::new("{\"a\": 1}  \n [2,\n3]\n4 true".bytes().map(Ok));
+        assert_eq!(parser.position(), (1, 1));
+        assert_eq!(parser.byte_offset(), 0);
+
+        assert_eq!(parser.parse().unwrap(), jobj!["a" => jnum!("1")]);
+        assert_eq!(parser.position(), (1, 9));
+        assert_eq!(parser.byte_offset(), 8);
+
+        assert_eq!(parser.parse().unwrap(), jarr![jnum!("2"), jnum!("3")]);
+        assert_eq!(parser.position(), (3, 3));
+        assert_eq!(parser.byte_offset(), 18);
+
+        // Numbers are terminated by peeking at the following byte, which
+        // must not count as consumed
+        assert_eq!(parser.parse().unwrap(), jnum!("4"));
+        assert_eq!(parser.position(), (4, 2));
+        assert_eq!(parser.byte_offset(), 20);
+
+        assert_eq!(parser.parse().unwrap(), jbool!(true));
+        assert_eq!(parser.position(), (4, 7));
+        assert_eq!(parser.byte_offset(), 25);
+    }
+
+    #[test]
+    fn test_utf8() {
+        use parser::Parser;
+
+        let parse = |b: &[u8]| Parser::new(b.iter().map(|b| Ok(*b))).parse();
+        assert_eq!(Json::from_str("\"caf\u{e9}\"").unwrap(), jstr!("caf\u{e9}"));
+        assert_eq!(Json::from_str("{\"\u{3b1}\u{3b2}\": [\"\u{20ac}\\n\u{1f600}\", \"\u{7ff}\u{800}\u{ffff}\u{10000}\u{10ffff}\"]}").unwrap(),
+                   jobj!["\u{3b1}\u{3b2}" => jarr![jstr!("\u{20ac}\n\u{1f600}"), jstr!("\u{7ff}\u{800}\u{ffff}\u{10000}\u{10ffff}")]]);
+        // Mixed with escapes, which decode to the same characters
+        assert_eq!(Json::from_str("\"\u{e9}\\u00e9\u{e9}\"").unwrap(), jstr!("\u{e9}\u{e9}\u{e9}"));
+
+        // Each is reported at the first byte which cannot be valid
+        let cases: &[(&[u8], usize)] = &[
+            (b"\"\x80\"", 2),              // lone continuation byte
+            (b"\"a\xc3\"", 4),             // truncated sequence
+            (b"\"\xe2\x82\"", 4),
+            (b"\"\xc3\xa9\xa9\"", 4),
+            (b"\"\xc0\xaf\"", 2),          // overlong
+            (b"\"\xc1\xbf\"", 2),
+            (b"\"\xe0\x80\xaf\"", 3),
+            (b"\"\xf0\x8f\xbf\xbf\"", 3),
+            (b"\"\xed\xa0\x80\"", 3),      // surrogate
+            (b"\"\xf4\x90\x80\x80\"", 3),  // past U+10FFFF
+            (b"\"\xf5\x80\x80\x80\"", 2),
+            (b"\"\xff\"", 2),
+            (b"[\"ok\", {\"\xe9\": 1}]", 11),
+        ];
+        for &(input, col) in cases {
+            match parse(input) {
+                Err(Error(ErrorInner::Parser(e))) => {
+                    assert_eq!(e.col, col, "{:?}", input);
+                    match *e.error.innermost() {
+                        ErrorType::InvalidUtf8 => {},
+                        ref e => panic!("wrong error type {:?} for {:?}", e, input),
+                    }
+                }
+                _ => panic!("wrong error return type for {:?}", input),
+            }
+        }
+        // Input ending within a sequence leaves the string unterminated
+        for input in &[&b"\"\xe2\x82"[..], b"\"\xc3", b"[1, \"\xf0\x9f\x98"] {
+            match parse(input) {
+                Err(Error(ErrorInner::Parser(e))) => match *e.error.innermost() {
+                    ErrorType::UnterminatedString(..) => {},
+                    ref e => panic!("wrong error type {:?} for {:?}", e, input),
+                },
+                _ => panic!("wrong error return type for {:?}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_key_len() {
+        use parser::{Parser, ErrorType};
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.set_max_key_len(4);
+            parser.parse()
+        };
+        assert_eq!(parse("{\"abcd\": \"values are not limited\"}").unwrap(),
+                   jobj!["abcd" => jstr!("values are not limited")]);
+        assert_eq!(parse("{\"a\\nc\": {\"\": 1}}").unwrap(), jobj!["a\nc" => jobj!["" => jnum!("1")]]);
+        match parse("{\"a\": {\"abcde\": 1}}") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                // At the first byte past the limit
+                assert_eq!(e.col, 13);
+                match *e.error.innermost() {
+                    ErrorType::KeyTooLong => {},
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+        assert!(parse("{\"abc\\u0041d\": 1}").is_err());
+        assert!(parse(&format!("{{\"{}", "k".repeat(1_000_000))).is_err());
+
+        // No limit by default
+        let long = "k".repeat(100_000);
+        assert!(Json::from_str(&format!("{{\"{}\": 1}}", long)).is_ok());
+    }
+
+    #[test]
+    fn test_allow_leading_plus() {
+        use parser::Parser;
+
+        let parse = |s: &str, allow: bool| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.allow_leading_plus(allow);
+            parser.parse()
+        };
+        assert_eq!(parse("+1", true).unwrap(), jnum!("1"));
+        assert_eq!(parse("[+1.5, +0, -2, 3e+2]", true).unwrap(),
+                   jarr![jnum!("1.5"), jnum!("0"), jnum!("-2"), jnum!("3e+2")]);
+        assert_eq!(parse("+1.5", true).unwrap().to_string(), "1.5");
+        for bad in &["++1", "+-1", "-+1", "+", "+e1", "+.5", "1+"] {
+            assert!(parse(bad, true).is_err(), "{}", bad);
+        }
+
+        for strict in &["+1", "+1.5", "[+1]"] {
+            match parse(strict, false) {
+                Err(Error(ErrorInner::Parser(e))) => match *e.error.innermost() {
+                    ErrorType::UnknownIdent => {},
+                    _ => panic!("wrong error type for {}", strict),
+                },
+                _ => panic!("wrong error return type for {}", strict),
+            }
+        }
+        assert!(Json::from_str("+1").is_err());
+    }
+
+    #[test]
+    fn test_max_number_len() {
+        use parser::{Parser, ErrorType};
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.set_max_number_len(8);
+            parser.parse()
+        };
+        assert_eq!(parse("12345678").unwrap(), jnum!("12345678"));
+        assert_eq!(parse("[-1.5e-10, 1]").unwrap(), jarr![jnum!("-1.5e-10"), jnum!("1")]);
+        match parse("[123456789]") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.col, 10);
+                match *e.error.innermost() {
+                    ErrorType::NumberTooLong => {},
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+        assert!(parse("-1.5e-100").is_err());
+        assert!(parse(&"1".repeat(1_000_000)).is_err());
+
+        // No limit by default
+        let long = "1".repeat(1_000_000);
+        assert_eq!(Json::from_str(&long).unwrap(), jnum!(long));
+    }
+
+    #[test]
+    fn test_keywords() {
+        use parser::Parser;
+
+        assert!(Json::from_str("nil").is_err());
+        assert!(Json::from_str("[nil]").is_err());
+        assert!(Json::from_str("nul").is_err());
+        assert!(Json::from_str("True").is_err());
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.add_keyword("nil", jnull!());
+            parser.add_keyword("yes", jbool!(true));
+            parser.add_keyword("no", jbool!(false));
+            parser.add_keyword("nill", jstr!("nill"));
+            parser.parse()
+        };
+        assert_eq!(parse("nil").unwrap(), jnull!());
+        assert_eq!(parse("nill").unwrap(), jstr!("nill"));
+        assert_eq!(parse("[yes, no, null, true]").unwrap(),
+                   jarr![jbool!(true), jbool!(false), jnull!(), jbool!(true)]);
+        assert_eq!(parse("{\"k\": nil}").unwrap(), jobj!["k" => jnull!()]);
+        assert!(parse("ni").is_err());
+        assert!(parse("[nope]").is_err());
+
+        let mut parser = Parser::new("[NaN_2]".bytes().map(Ok));
+        parser.add_keyword("NaN_2", jnull!());
+        assert_eq!(parser.parse().unwrap(), jarr![jnull!()]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_keyword_bad_start() {
+        ::parser::Parser::new("".bytes().map(Ok)).add_keyword("_nil", Json::from(()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_keyword_bad_char() {
+        ::parser::Parser::new("".bytes().map(Ok)).add_keyword("n\u{ef}l", Json::from(()));
+    }
+
+    #[test]
+    fn test_reset() {
+        use parser::Parser;
+
+        let mut parser = Parser::new("[[[1".bytes().map(Ok));
+        parser.set_max_depth(3);
+        assert!(parser.parse().is_err());
+
+        // Nesting left by the failed parse does not count against the limit
+        parser.reset("\n [[[2]]]".bytes().map(Ok));
+        assert_eq!(parser.position(), (1, 1));
+        assert_eq!(parser.parse().unwrap(), jarr![jarr![jarr![jnum!("2")]]]);
+        assert_eq!(parser.position(), (2, 9));
+        assert_eq!(parser.byte_offset(), 9);
+
+        for (n, doc) in ["{\"n\": 0}", "{\"n\": 1}", "{\"n\": 2}"].iter().enumerate() {
+            parser.reset(doc.bytes().map(Ok));
+            assert_eq!(parser.byte_offset(), 0);
+            assert_eq!(parser.parse().unwrap(), jobj!["n" => Json::from(n)]);
+            assert_eq!(parser.position(), (1, 9));
+            assert_eq!(parser.byte_offset(), 8);
+        }
+
+        // Errors are reported relative to the new input, and options stay set
+        parser.reset("[[[[]]]]".bytes().map(Ok));
+        match parser.parse() {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!((e.line, e.col), (1, 4));
+                match *e.error.innermost() {
+                    ErrorType::DepthLimitExceeded => {},
+                    ref e => panic!("wrong error type {:?}", e),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn test_position_overflow() {
+        use parser::{Parser, ErrorType};
+
+        let mut parser = Parser::new("[1, 2,\n 3, x]".bytes().map(Ok));
+        parser.line = usize::MAX - 1;
+        parser.col = usize::MAX - 2;
+        match parser.parse() {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.line, usize::MAX);
+                assert_eq!(e.col, 5);
+                match *e.error.innermost() {
+                    ErrorType::UnknownIdent => {},
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        let mut parser = Parser::new("[1, 2, 3]".bytes().map(Ok));
+        parser.col = usize::MAX - 2;
+        assert!(parser.parse().is_ok());
+        assert_eq!(parser.col, usize::MAX);
+    }
+
+    #[test]
+    fn test_reject_duplicate_keys() {
+        use parser::Parser;
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.reject_duplicate_keys(true);
+            parser.parse()
+        };
+        // Equal keys in different objects are fine
+        assert_eq!(parse("{\"a\": {\"a\": 1}, \"b\": [{\"a\": 2}, {\"a\": 3}]}").unwrap(),
+                   jobj!["a" => jobj!["a" => jnum!("1")], "b" => jarr![jobj!["a" => jnum!("2")], jobj!["a" => jnum!("3")]]]);
+
+        match parse("{\"a\": {\"k\": 1, \"k\": 2}}") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                // At the second occurrence
+                assert_eq!(e.col, 16);
+                match *e.error.innermost() {
+                    ErrorType::DuplicateKey(ref key) => assert_eq!(key, "k"),
+                    _ => panic!("wrong error type"),
+                }
+                assert_eq!(e.error.code(), "duplicate_key");
+                assert_eq!(e.to_string(), "1:16: error parsing value for key `a`: duplicate object key `k`");
+            }
+            _ => panic!("wrong error return type"),
+        }
+        // Keys are compared unescaped
+        assert!(parse("{\"a\": 1, \"b\": 2, \"\\u0061\": 3}").is_err());
+
+        let mut parser = Parser::new("{\"x\": 1, \"x\": 2}".bytes().map(Ok));
+        parser.reject_duplicate_keys(true);
+        let mut count = 0;
+        assert!(parser.parse_object_streaming(|_, _| { count += 1; Ok(()) }).is_err());
+        assert_eq!(count, 1);
+
+        // Kept by default
+        assert_eq!(Json::from_str("{\"k\": 1, \"k\": 2}").unwrap(), jobj!["k" => jnum!("1"), "k" => jnum!("2")]);
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        use parser::{ArrayElements, Parser};
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.allow_trailing_commas(true);
+            parser.parse()
+        };
+        assert_eq!(parse("[1, 2,]").unwrap(), jarr![jnum!("1"), jnum!("2")]);
+        assert_eq!(parse("{\"a\": 1,}").unwrap(), jobj!["a" => jnum!("1")]);
+        assert_eq!(parse("{\"a\": [[],\n ],\n \"b\": {\"c\": null , } , }").unwrap(),
+                   jobj!["a" => jarr![jarr![]], "b" => jobj!["c" => jnull!()]]);
+        // Commas without elements are still errors
+        for bad in &["[,]", "{,}", "[1,,]", "{\"a\": 1,,}", "[1,", "{\"a\": 1,"] {
+            assert!(parse(bad).is_err(), "{}", bad);
+        }
+
+        let mut parser = Parser::new("[1, \"x\",\n]".bytes().map(Ok));
+        parser.allow_trailing_commas(true);
+        let elems: Vec<Json> = ArrayElements::from_parser(parser).collect::<Result<_, _>>().unwrap();
+        assert_eq!(elems, vec![jnum!("1"), jstr!("x")]);
+
+        // Rejected by default
+        assert!(Json::from_str("[1, 2,]").is_err());
+        assert!(Json::from_str("{\"a\": 1,}").is_err());
+        assert!(ArrayElements::new("[1,]".bytes().map(Ok)).any(|e| e.is_err()));
+    }
+
+    #[test]
+    fn test_comments() {
+        use parser::Parser;
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.allow_comments(true);
+            parser.parse()
+        };
+        let input = "// leading\n{\n  \"a\": 1, // one\n  /* block\n     comment */ \"b\": [true/**/, null/* ** */],\n  \"c\": 2// end\n}\n// trailing";
+        assert_eq!(parse(input).unwrap(),
+                   jobj!["a" => jnum!("1"), "b" => jarr![jbool!(true), jnull!()], "c" => jnum!("2")]);
+        assert_eq!(parse("/***/1/**/").unwrap(), jnum!("1"));
+        assert_eq!(parse("[1 // no newline at the end\n]").unwrap(), jarr![jnum!("1")]);
+        // Comment markers inside strings are text
+        assert_eq!(parse("\"// /* */\"").unwrap(), jstr!("// /* */"));
+
+        match parse("[1, /* unterminated *") {
+            Err(Error(ErrorInner::Parser(e))) => match *e.error.innermost() {
+                ErrorType::UnexpectedEOF => {},
+                _ => panic!("wrong error type"),
+            },
+            _ => panic!("wrong error return type"),
+        }
+        assert!(parse("[1, / 2]").is_err());
+        assert!(parse("/* a */ */ 1").is_err());
+
+        // Rejected by default
+        assert!(Json::from_str("// comment\n1").is_err());
+        assert!(Json::from_str("/* c */ 1").is_err());
+        assert!(Json::from_str("[true/**/]").is_err());
+    }
+
+    #[test]
+    fn test_single_quotes() {
+        use parser::Parser;
+
+        let parse = |s: &str| {
+            let mut parser = Parser::new(s.bytes().map(Ok));
+            parser.allow_single_quotes(true);
+            parser.parse()
+        };
+        assert_eq!(parse("'hello'").unwrap(), jstr!("hello"));
+        assert_eq!(parse("''").unwrap(), jstr!(""));
+        assert_eq!(parse("{'a': \"x\", \"b\": 'y'}").unwrap(), jobj!["a" => jstr!("x"), "b" => jstr!("y")]);
+        // Each quote may appear unescaped inside the other
+        assert_eq!(parse("['say \"hi\"', \"it's\"]").unwrap(), jarr![jstr!("say \"hi\""), jstr!("it's")]);
+        assert_eq!(parse("['it\\'s \\\"x\\\"\\n', \"\\'\"]").unwrap(), jarr![jstr!("it's \"x\"\n"), jstr!("'")]);
+
+        match parse("['abc") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.col, 2);
+                match *e.error.innermost() {
+                    ErrorType::UnterminatedString(1, 2) => {},
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+        assert!(parse("['it's']").is_err());
+        assert!(parse("'a\"").is_err());
+
+        // Double quotes only by default
+        assert!(Json::from_str("'hello'").is_err());
+        assert!(Json::from_str("{'a': 1}").is_err());
+        assert!(Json::from_str("\"\\'\"").is_err());
+        assert_eq!(Json::from_str("\"it's\"").unwrap(), jstr!("it's"));
+    }
+
+    #[test]
+    fn test_object_value_error() {
+        use parser::ErrorType;
+
+        match Json::from_str("{\"a\": 1,\n \"k\": }") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!((e.line, e.col), (2, 7));
+                match *e.kind() {
+                    ErrorType::InObjectValue(ref key, _) => assert_eq!(key, "k"),
+                    _ => panic!("wrong error type"),
+                }
+                match *e.kind().innermost() {
+                    ErrorType::UnknownIdent => {}
+                    _ => panic!("wrong innermost error type"),
+                }
+                assert_eq!(e.to_string(), "2:7: error parsing value for key `k`: unknown ident");
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        match Json::from_str("{\"outer\": {\"inner\": [1, 2+]}}") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!((e.line, e.col), (1, 26));
+                assert_eq!(e.to_string(), "1:26: error parsing value for key `outer`: \
+                                           error parsing value for key `inner`: \
+                                           error parsing array element 1: unexpected character +");
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        // Errors in the keys themselves are not attributed to a key
+        match Json::from_str("{\"a\": 1, 5: 2}") {
+            Err(Error(ErrorInner::Parser(e))) => assert_eq!(e.to_string(), "1:10: expected string"),
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn test_coerce_scalar_keys() {
+        use parser::Parser;
+
+        let input = "{1: \"a\", true: \"b\", -2.5e1: [], null: 0, \"s\": 1, false:2}";
+        let mut parser = Parser::new(input.bytes().map(Ok));
+        parser.coerce_scalar_keys(true);
+        assert_eq!(parser.parse().unwrap(),
+                   Json::from_str("{\"1\": \"a\", \"true\": \"b\", \"-2.5e1\": [], \"null\": 0, \"s\": 1, \"false\": 2}").unwrap());
+
+        // Custom keywords which are containers are still rejected
+        let mut parser = Parser::new("{nil: 1, empty: 2}".bytes().map(Ok));
+        parser.coerce_scalar_keys(true);
+        parser.add_keyword("nil", Json::from(()));
+        parser.add_keyword("empty", Json::from(Vec::<Json>::new()));
+        assert!(parser.parse().is_err());
+
+        // Objects and arrays are never keys
+        for bad in &["{[1]: 2}", "{{}: 2}", "{nope: 2}"] {
+            let mut parser = Parser::new(bad.bytes().map(Ok));
+            parser.coerce_scalar_keys(true);
+            assert!(parser.parse().is_err(), "{}", bad);
+        }
+
+        // Strict by default
+        for bad in &["{1: \"a\"}", "{true: \"b\"}", "{null: 0}"] {
+            assert!(Json::from_str(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        use parser::ErrorType;
+
+        for &(input, line, col) in &[("\"abc", 1, 1), ("[\"a\", \"b", 1, 7), ("{\"k\": [1,\n  \"x\\u00", 2, 3), ("{\"key", 1, 2)] {
+            match Json::from_str(input) {
+                Err(Error(ErrorInner::Parser(e))) => {
+                    match *e.error.innermost() {
+                        ErrorType::UnterminatedString(l, c) => assert_eq!((l, c), (line, col)),
+                        _ => panic!("wrong error type for {}", input),
+                    }
+                    assert_eq!((e.line, e.col), (line, col));
+                }
+                _ => panic!("wrong error return type for {}", input),
+            }
+        }
+
+        // EOF before a string starts is just an EOF
+        match Json::from_str("{\"a\": 1,") {
+            Err(Error(ErrorInner::Parser(e))) => match e.error {
+                ErrorType::UnexpectedEOF => {}
+                _ => panic!("wrong error type"),
+            },
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn test_unicode_whitespace() {
+        use parser::{ErrorType, Parser};
+
+        let input = "\u{feff}{\u{a0}\"a\":\u{a0}[1\u{a0},\u{3000}true\u{2028}]\u{202f}}\u{a0}";
+        let mut parser = Parser::new(input.bytes().map(Ok));
+        parser.allow_unicode_whitespace(true);
+        assert_eq!(parser.parse().unwrap(), Json::from_str("{\"a\": [1, true]}").unwrap());
+
+        // Spaces inside strings are kept
+        let mut parser = Parser::new("[\"\u{a0}\"]".bytes().map(Ok));
+        parser.allow_unicode_whitespace(true);
+        assert_eq!(parser.parse().unwrap(), Json::from_str("[\"\u{a0}\"]").unwrap());
+
+        // Other non-ASCII characters are still errors
+        for &(bad, ch) in &[("[1,\u{e9}2]", '\u{e9}'), ("[1\u{2026}]", '\u{2026}'), ("[\u{1f600}]", '\u{1f600}')] {
+            let mut parser = Parser::new(bad.bytes().map(Ok));
+            parser.allow_unicode_whitespace(true);
+            let e = parser.parse_value().unwrap_err();
+            match e.error {
+                ErrorType::UnexpectedCharacter(c) => assert_eq!(c, ch),
+                _ => panic!("wrong error type for {}", bad),
+            }
+            assert_eq!(e.col, bad.find(ch).unwrap() + 1);
+        }
+        // As is invalid UTF-8
+        let mut parser = Parser::new(b"[1,\xe2\x80 2]".iter().map(|b| Ok(*b)));
+        parser.allow_unicode_whitespace(true);
+        match parser.parse_value().unwrap_err().error {
+            ErrorType::InvalidUtf8 => {},
+            e => panic!("wrong error type {:?}", e),
+        }
+
+        // Strict by default
+        assert!(Json::from_str("[1,\u{a0}2]").is_err());
+        assert!(Json::from_str("[1\u{a0}]").is_err());
+    }
+
+    #[test]
+    fn test_parse_one() {
+        use parser::Parser;
+
+        let mut parser = Parser::new("  {\"len\":5}BINARY".bytes().map(Ok));
+        assert_eq!(parser.parse_one().unwrap(), Json::from_str("{\"len\": 5}").unwrap());
+        assert_eq!(parser.byte_offset(), 11);
+        let (peeked, rest) = parser.into_inner();
+        assert_eq!(peeked, None);
+        let rest: Vec<u8> = rest.map(|b| b.unwrap()).collect();
+        assert_eq!(rest, b"BINARY");
+
+        // A number ends only when the next byte is seen
+        let mut parser = Parser::new("[1] 23 true\nrest".bytes().map(Ok));
+        assert_eq!(parser.parse_one().unwrap(), Json::from_str("[1]").unwrap());
+        assert_eq!(parser.parse_one().unwrap(), Json::from(23));
+        assert_eq!(parser.parse_one().unwrap(), Json::from(true));
+        let (peeked, rest) = parser.into_inner();
+        assert_eq!(peeked, Some(b'\n'));
+        let rest: Vec<u8> = rest.map(|b| b.unwrap()).collect();
+        assert_eq!(rest, b"rest");
+    }
+
+    #[test]
+    fn test_expected_colon() {
+        use parser::ErrorType;
+
+        for &(input, col) in &[("{\"k\" 1}", 6), ("{\"k\"}", 5), ("{\"a\": 1, \"k\",}", 13)] {
+            match Json::from_str(input) {
+                Err(Error(ErrorInner::Parser(e))) => {
+                    match e.error {
+                        ErrorType::ExpectedColon => {}
+                        _ => panic!("wrong error type for {}", input),
+                    }
+                    assert_eq!((e.line, e.col), (1, col));
+                    assert_eq!(e.to_string(), format!("1:{}: expected `:` after object key", col));
+                }
+                _ => panic!("wrong error return type for {}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_source_error() {
+        use std::{error, fmt, io};
+        use parser::{ErrorType, Parser};
+
+        #[derive(PartialEq, Debug)]
+        enum MockError { Corrupt(usize) }
+
+        impl fmt::Display for MockError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match *self { MockError::Corrupt(n) => write!(f, "corrupt block {}", n) }
+            }
+        }
+
+        impl error::Error for MockError {}
+
+        let input = vec![Ok(b'['), Ok(b'1'), Ok(b','), Err(MockError::Corrupt(3)), Ok(b']')];
+        let e = Parser::from_fallible(input.into_iter()).parse_value().unwrap_err();
+        assert_eq!((e.line, e.col), (1, 3));
+        match e.error {
+            ErrorType::Source(ref src) => {
+                assert_eq!(src.downcast_ref::<MockError>(), Some(&MockError::Corrupt(3)));
+            }
+            _ => panic!("wrong error type"),
+        }
+        assert_eq!(e.to_string(), "1:3: corrupt block 3");
+
+        let input = b"[2]".iter().cloned().map(Ok::<u8, MockError>);
+        assert_eq!(Parser::from_fallible(input).parse().unwrap(),
+                   Json::from_str("[2]").unwrap());
+
+        // IO errors are still reported as such
+        let input = vec![Ok(b'['), Err(io::Error::other("oops"))];
+        match Parser::new(input.into_iter()).parse_value().unwrap_err().error {
+            ErrorType::Io(ref e) => assert_eq!(e.to_string(), "oops"),
+            _ => panic!("wrong error type"),
+        }
+    }
+
+    #[test]
+    fn test_array_element_error() {
+        let mut input = String::from("[");
+        for i in 0..42 {
+            input.push_str(&format!("{},\n", i));
+        }
+        input.push_str("{\"a\": [true, tru]}, 43]");
+        match Json::from_str(&input) {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!((e.line, e.col), (43, 17));
+                match e.error {
+                    ErrorType::InArrayElement(42, ref inner) => match **inner {
+                        ErrorType::InObjectValue(ref key, ref inner) => {
+                            assert_eq!(key, "a");
+                            match **inner {
+                                ErrorType::InArrayElement(1, ref inner) => match **inner {
+                                    ErrorType::UnknownIdent => {}
+                                    _ => panic!("wrong innermost error type"),
+                                },
+                                _ => panic!("wrong inner array error"),
+                            }
+                        }
+                        _ => panic!("wrong object error"),
+                    },
+                    _ => panic!("wrong error type"),
+                }
+                assert_eq!(e.to_string(), "43:17: error parsing array element 42: error parsing value for key `a`: \
+                                           error parsing array element 1: unknown ident");
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        // Separators and the brackets themselves are not elements
+        match Json::from_str("[1, 2 3]") {
+            Err(Error(ErrorInner::Parser(e))) => match e.error {
+                ErrorType::Expected { .. } => {}
+                _ => panic!("wrong error type"),
+            },
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn test_expected_token() {
+        for &(input, expected, found, col) in &[
+            ("[1 2]", "`,` or `]`", '2', 4),
+            ("{\"k\":1 \"j\":2}", "`,` or `}`", '"', 8),
+            ("[[true] x]", "`,` or `]`", 'x', 9),
+            // Non-ASCII characters are decoded whole
+            ("[1 \u{e9}]", "`,` or `]`", '\u{e9}', 4),
+            ("{\"k\": 1 \u{2192}}", "`,` or `}`", '\u{2192}', 9),
+        ] {
+            match Json::from_str(input) {
+                Err(Error(ErrorInner::Parser(e))) => {
+                    assert_eq!((e.line, e.col), (1, col), "{:?}", input);
+                    match *e.error.innermost() {
+                        ErrorType::Expected { expected: exp, found: fnd } => {
+                            assert_eq!(exp, expected);
+                            assert_eq!(fnd, found);
+                        }
+                        _ => panic!("wrong error type for {:?}", input),
+                    }
+                }
+                _ => panic!("wrong error return type for {:?}", input),
+            }
+        }
+
+        for &(input, msg) in &[
+            ("[1 2]", "1:4: expected `,` or `]`, found `2`"),
+            ("[1 \u{e9}]", "1:4: expected `,` or `]`, found `\u{e9}`"),
+            ("{\"k\":1 \"j\":2}", "1:8: expected `,` or `}`, found `\"`"),
+        ] {
+            match Json::from_str(input) {
+                Err(Error(ErrorInner::Parser(e))) => assert_eq!(e.to_string(), msg),
+                _ => panic!("wrong error return type for {:?}", input),
+            }
+        }
+
+        match Json::array_elements_from_reader(&b"{}"[..]).next() {
+            Some(Err(Error(ErrorInner::Parser(e)))) => {
+                assert_eq!(e.to_string(), "1:1: expected `[`, found `{`");
+            }
+            _ => panic!("expected an error"),
+        }
+        match Json::array_elements_from_reader("\u{3b1}".as_bytes()).next() {
+            Some(Err(Error(ErrorInner::Parser(e)))) => {
+                assert_eq!(e.to_string(), "1:1: expected `[`, found `\u{3b1}`");
+            }
+            _ => panic!("expected an error"),
+        }
+
+        // A byte which does not start a character is invalid UTF-8
+        match Json::from_slice(b"[1 \xff]") {
+            Err(Error(ErrorInner::Parser(e))) => match *e.kind().innermost() {
+                ErrorType::InvalidUtf8 => {}
+                ref e => panic!("wrong error type {:?}", e),
+            },
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn test_validate_stream() {
+        let input = "{\"a\": [1, 2]}\n[tru, 3]\n\"ok\"\n";
+        let errs = ::validate_stream(input.as_bytes());
+        assert_eq!(errs.len(), 1);
+        match errs[0] {
+            Error(ErrorInner::Parser(ref e)) => {
+                assert_eq!(e.line, 2);
+                match *e.error.innermost() {
+                    ErrorType::UnknownIdent => {}
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        // Checking continues after each bad line, including a final one
+        // with no newline, and values may share a line
+        let errs = ::validate_stream(&b"1 2\n[1 2]\n{}\n{\"a\" 1}\n\n[1, [2, [3"[..]);
+        let lines: Vec<_> = errs.iter().map(|e| match *e {
+            Error(ErrorInner::Parser(ref e)) => e.line,
+            _ => panic!("wrong error return type"),
+        }).collect();
+        assert_eq!(lines, vec![2, 4, 6]);
+
+        assert!(::validate_stream(&b""[..]).is_empty());
+        assert!(::validate_stream(&b" \n\n"[..]).is_empty());
+
+        // A value failing deep inside nesting leaves no nesting behind
+        let mut parser = ::parser::Parser::new("[[x]]\n[{\"a\": 1 2}]\n[[1]]\n".bytes().map(Ok));
+        parser.set_max_depth(2);
+        assert_eq!(parser.validate_stream().len(), 2);
+    }
+
+    #[test]
+    fn test_error_new() {
+        use parser::Error as ParserError;
+
+        let err = ParserError::new(3, 7, ErrorType::UnknownIdent);
+        assert_eq!(err.to_string(), "3:7: unknown ident");
+
+        match Json::from_str("[1,\n\n      x]") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                let made = ParserError::new(3, 7, ErrorType::InArrayElement(1, Box::new(ErrorType::UnknownIdent)));
+                assert_eq!(made.to_string(), e.to_string());
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        let err: Error = From::from(ParserError::new(1, 2, ErrorType::MalformedNumber));
+        assert_eq!(err.to_string(), "malformed number");
+    }
+
+    #[test]
+    fn test_error_to_json() {
+        match Json::from_str("{\"a\": [1,\n  2,\n  tru]}") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.to_json().to_string(), "{\"line\": 3, \"col\": 6, \"byte_offset\": 20, \"code\": \"unknown_ident\", \
+                                                     \"message\": \"3:6: error parsing value for key `a`: \
+                                                     error parsing array element 2: unknown ident\"}");
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        match Json::from_str("[\"abc") {
+            Err(Error(ErrorInner::Parser(e))) => {
+                let json = e.to_json();
+                assert_eq!(json.get("byte_offset"), Some(&Json::from(1usize)));
+                assert_eq!(json.get("code").and_then(Json::string), Some("unterminated_string"));
+            }
+            _ => panic!("wrong error return type"),
+        }
+
+        let made = ::parser::Error::new(2, 5, ErrorType::MalformedNumber).to_json();
+        assert_eq!(made.get("byte_offset"), Some(&Json::from(())));
+        assert_eq!(made.get("message").and_then(Json::string), Some("2:5: malformed number"));
+        let made = ::parser::Error::new_at(2, 5, 17, ErrorType::MalformedNumber).to_json();
+        assert_eq!(made.get("byte_offset"), Some(&Json::from(17usize)));
+    }
+
+    #[test]
+    fn test_parse_expecting() {
+        use parser::Parser;
+        use JsonType;
+
+        let parse = |s: &str, allowed: &[JsonType]| Parser::new(s.bytes().map(Ok)).parse_expecting(allowed);
+        assert_eq!(parse(" {\"a\": 1}", &[JsonType::Object]).unwrap(), jobj!["a" => jnum!("1")]);
+        assert_eq!(parse("[]", &[JsonType::Object, JsonType::Array]).unwrap(), jarr![]);
+
+        match parse("\n  \"str\"", &[JsonType::Object]) {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.to_string(), "2:3: expected an object, found a string");
+                match e.error {
+                    ErrorType::UnexpectedType { ref allowed, found: JsonType::String } => {
+                        assert_eq!(*allowed, vec![JsonType::Object]);
+                    }
+                    _ => panic!("wrong error type"),
+                }
+            }
+            _ => panic!("wrong error return type"),
+        }
+        match parse("12", &[JsonType::Object, JsonType::Array, JsonType::Null]) {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.to_string(), "1:1: expected an object, an array or null, found a number");
+            }
+            _ => panic!("wrong error return type"),
+        }
+        // Syntax errors are reported as usual
+        match parse("[1, x]", &[JsonType::Object]) {
+            Err(Error(ErrorInner::Parser(e))) => match *e.error.innermost() {
+                ErrorType::UnknownIdent => {}
+                _ => panic!("wrong error type"),
+            },
+            _ => panic!("wrong error return type"),
+        }
+
+        let mut parser = Parser::new(")]}'\n[1]".bytes().map(Ok));
+        parser.strip_xssi_prefix(true);
+        assert_eq!(parser.parse_expecting(&[JsonType::Array]).unwrap(), jarr![jnum!("1")]);
+    }
+
+    #[test]
+    fn test_parse_object_streaming() {
+        use parser::Parser;
+
+        let input = "{\"a\": 1, \"b\": [true], \"a\": {\"c\": null}}";
+        let mut seen = vec![];
+        Parser::new(input.bytes().map(Ok)).parse_object_streaming(|key, val| {
+            seen.push((key, val));
+            Ok(())
+        }).unwrap();
+        assert_eq!(Json::from(seen), Json::from_str(input).unwrap());
+
+        // A callback error stops parsing and is returned as it is
+        let mut count = 0;
+        let res = Parser::new(input.bytes().map(Ok)).parse_object_streaming(|_, val| {
+            count += 1;
+            val.require_object().map(|_| ())
+        });
+        assert_eq!(count, 1);
+        assert_eq!(res.unwrap_err().to_string(), "expected an object, found a number");
+
+        // Syntax errors after some members have been passed on
+        let mut count = 0;
+        match Parser::new("{\"a\": 1, \"b\": tru}".bytes().map(Ok)).parse_object_streaming(|_, _| { count += 1; Ok(()) }) {
+            Err(Error(ErrorInner::Parser(e))) => {
+                assert_eq!(e.to_string(), "1:18: error parsing value for key `b`: unknown ident");
+            }
+            _ => panic!("wrong error return type"),
+        }
+        assert_eq!(count, 1);
+
+        let mut count = 0;
+        Parser::new(" {}".bytes().map(Ok)).parse_object_streaming(|_, _| { count += 1; Ok(()) }).unwrap();
+        assert_eq!(count, 0);
+        match Parser::new("[1]".bytes().map(Ok)).parse_object_streaming(|_, _| Ok(())) {
+            Err(Error(ErrorInner::Parser(e))) => assert_eq!(e.to_string(), "1:1: expected `{`, found `[`"),
+            _ => panic!("wrong error return type"),
+        }
+    }
+
+    #[test]
+    fn test_keyword_boundary() {
+        for &(input, col) in &[("truer", 5), ("nullish", 5), ("falsey", 6), ("[true1]", 6), ("{\"a\": null\"b\"}", 11)] {
+            match Json::from_str(input) {
+                Err(Error(ErrorInner::Parser(e))) => {
+                    assert_eq!((e.line, e.col), (1, col), "{:?}", input);
+                    match *e.error.innermost() {
+                        ErrorType::UnknownIdent => {}
+                        _ => panic!("wrong error type for {:?}", input),
+                    }
+                }
+                _ => panic!("wrong error return type for {:?}", input),
+            }
+        }
+
+        assert_eq!(Json::from_str("[true,false]").unwrap(), jarr![jbool!(true), jbool!(false)]);
+        assert_eq!(Json::from_str("[true]").unwrap(), jarr![jbool!(true)]);
+        assert_eq!(Json::from_str("{\"a\":null}").unwrap(), jobj!["a" => jnull!()]);
+        assert_eq!(Json::from_str("true").unwrap(), jbool!(true));
+        assert_eq!(Json::from_str("null \n").unwrap(), jnull!());
+
+        // Custom keywords follow the same rule
+        let mut parser = ::parser::Parser::new("[NaN, NaNa]".bytes().map(Ok));
+        parser.add_keyword("NaN", Json::from(()));
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_nul() {
+        for &(input, ch, line, col) in &[
+            ("\0", '\0', 1, 1),
+            ("[1,\0 2]", '\0', 1, 4),
+            ("[1, \n \0]", '\0', 2, 2),
+            ("[true\0]", '\0', 1, 6),
+            ("[1\0]", '\0', 1, 3),
+            ("{\"a\": \0}", '\0', 1, 7),
+            ("[\"ab\0c\"]", '\0', 1, 5),
+            ("\"\x01\"", '\x01', 1, 2),
+            ("\"a\tb\"", '\t', 1, 3),
+        ] {
+            let mut parser = ::parser::Parser::new(input.bytes().map(Ok));
+            parser.reject_control_characters(true);
+            match parser.parse() {
+                Err(Error(ErrorInner::Parser(e))) => {
+                    match *e.error.innermost() {
+                        ErrorType::UnexpectedCharacter(c) => assert_eq!(c, ch, "{:?}", input),
+                        _ => panic!("wrong error type for {:?}", input),
+                    }
+                    assert_eq!((e.line, e.col), (line, col), "{:?}", input);
+                }
+                _ => panic!("wrong error return type for {:?}", input),
+            }
+        }
+
+        // Escaped, a NUL is fine
+        assert_eq!(Json::from_str("\"a\\u0000b\"").unwrap().string(), Some("a\0b"));
+
+        // By default raw control characters in strings are kept, though
+        // between tokens they are always errors
+        assert_eq!(Json::from_str("\"a\tb\"").unwrap().string(), Some("a\tb"));
+        assert_eq!(Json::from_str("[\"a\nb\"]").unwrap(), jarr![jstr!("a\nb")]);
+        assert_eq!(Json::from_str("\"\0\"").unwrap().string(), Some("\0"));
+        assert!(Json::from_str("[1,\0 2]").is_err());
+    }
+
     #[test]
     fn test_error() {
         if let Err(Error(ErrorInner::Parser(e))) = Json::from_str("10+5") {
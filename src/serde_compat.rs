@@ -0,0 +1,65 @@
+// Stringly-Typed JSON Library for Rust
+// Written in 2015 by
+//   Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Support for serde's `with` attribute
+//!
+//! Lets a field of a derived struct hold a raw `Json` value, by annotating
+//! it with `#[serde(with = "strason::serde_compat")]`. Both functions go
+//! through the `Serialize` and `Deserialize` impls on `Json`, so as there,
+//! numbers are serialized as strings when the target format is not ours.
+//!
+
+use serde::{de, ser};
+
+use Json;
+
+/// Serialize a `Json` field
+pub fn serialize<S: ser::Serializer>(json: &Json, s: S) -> Result<S::Ok, S::Error> {
+    ser::Serialize::serialize(json, s)
+}
+
+/// Deserialize a `Json` field
+pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> Result<Json, D::Error> {
+    de::Deserialize::deserialize(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use Json;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Envelope {
+        id: u32,
+        #[serde(with = "::serde_compat")]
+        payload: Json,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let payload = Json::from_str("{\"b\": [true, null, \"x\"], \"a\": {}}").unwrap();
+        let env = Envelope { id: 7, payload: payload.clone() };
+
+        let text = serde_json::to_string(&env).unwrap();
+        assert_eq!(text, "{\"id\":7,\"payload\":{\"b\":[true,null,\"x\"],\"a\":{}}}");
+        let decoded: Envelope = serde_json::from_str(&text).unwrap();
+        assert_eq!(decoded, env);
+        // Member order survives
+        assert_eq!(decoded.payload.to_string(), payload.to_string());
+
+        // Numbers read from the outer format are kept as numbers
+        let decoded: Envelope = serde_json::from_str("{\"id\":1,\"payload\":[1,2.5]}").unwrap();
+        assert_eq!(decoded.payload, Json::from_str("[1, 2.5]").unwrap());
+    }
+}
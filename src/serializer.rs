@@ -15,78 +15,417 @@
 //! # Serialization support
 //!
 
-use std::{io, str};
-
-use {Json, JsonInner};
-
-fn serialize_string<W: io::Write>(s: &str, mut w: W) -> io::Result<()> {
-    try!(w.write(b"\""));
-    for ch in s.chars() {
-        match ch {
-            '\x07' => { try!(w.write(b"\\b")); }
-            '\x0c' => { try!(w.write(b"\\f")); }
-            '\n' => { try!(w.write(b"\\n")); }
-            '\r' => { try!(w.write(b"\\r")); }
-            '\t' => { try!(w.write(b"\\t")); }
-            '\\' => { try!(w.write(b"\\\\")); }
-            '"' => { try!(w.write(b"\\\"")); }
-            '\x20'...'\x7e' => { try!(w.write(&[ch as u8])); }
-#[cfg(feature="utf16")]
-            _ => {
-                let mut utf16 = [0u16; 2];
-                let subslice = ch.encode_utf16(&mut utf16);
-                for word in subslice.iter().cloned() {
-                    try!(write!(w, "\\u{:02x}{:02x}", word >> 8 as u8, word as u8));
+use std::{fmt, io, str};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use {canonical_number_exact, Json, JsonInner};
+
+/// A configurable Json serializer
+///
+/// The default configuration produces the same output as `Json::write_to`,
+/// with only the escaping that JSON requires.
+#[derive(Clone, Debug)]
+pub struct Writer {
+    escape_slash: bool,
+    escape_html: bool,
+    escape_non_ascii: bool,
+    max_width: Option<usize>,
+    indent: usize,
+    trailing_comma: bool,
+    normalize_numbers: bool,
+    on_duplicate_keys: DuplicateKeys,
+}
+
+/// How to serialize objects which have several members with the same key
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DuplicateKeys {
+    /// Write every member, as parsed. This is the default.
+    #[default]
+    Keep,
+    /// Write only the first member with each key
+    First,
+    /// Write only the last member with each key, which is the one most
+    /// parsers would keep
+    Last,
+    /// Fail with an `io::ErrorKind::InvalidData` error
+    Error,
+}
+
+/// Number of spaces by which each level of pretty output is indented,
+/// unless set otherwise
+const INDENT: usize = 2;
+
+/// A sink which counts the bytes written to it, failing once they exceed
+/// a limit so that measuring a large value stops early
+struct Measure {
+    len: usize,
+    limit: usize,
+}
+
+impl io::Write for Measure {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.len += buf.len();
+        if self.len > self.limit {
+            Err(io::Error::other("too wide"))
+        } else {
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+impl Default for Writer {
+    fn default() -> Writer {
+        Writer {
+            escape_slash: false,
+            escape_html: false,
+            escape_non_ascii: false,
+            max_width: None,
+            indent: INDENT,
+            trailing_comma: false,
+            normalize_numbers: false,
+            on_duplicate_keys: DuplicateKeys::Keep,
+        }
+    }
+}
+
+impl Writer {
+    /// Construct a new writer with the default configuration
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+
+    /// Whether to escape `/` as `\/`. Off by default.
+    pub fn escape_slash(&mut self, escape: bool) {
+        self.escape_slash = escape;
+    }
+
+    /// Whether to escape `<`, `>`, `&` and the JavaScript line terminators
+    /// U+2028 and U+2029 as `\uXXXX`, making the output safe to embed in an
+    /// HTML `<script>` element. Off by default.
+    pub fn escape_html(&mut self, escape: bool) {
+        self.escape_html = escape;
+    }
+
+    /// Whether to escape every non-ASCII character as `\uXXXX`, using a
+    /// surrogate pair for those outside the Basic Multilingual Plane, so
+    /// the output is plain ASCII. Off by default, in which case they are
+    /// written as UTF-8, unless the `utf16` feature is enabled; that always
+    /// escapes them, and this option cannot turn it off.
+    pub fn escape_non_ascii(&mut self, escape: bool) {
+        self.escape_non_ascii = escape;
+    }
+
+    /// Pretty-print the output, with arrays and objects kept on one line
+    /// if they fit within `width` bytes, counting indentation, and otherwise
+    /// written with one element per line and indented. `None`, the default,
+    /// writes everything on one line.
+    pub fn max_width(&mut self, width: Option<usize>) {
+        self.max_width = width;
+    }
+
+    /// The number of spaces by which pretty-printing indents each level of
+    /// nesting. Defaults to 2.
+    pub fn indent(&mut self, indent: usize) {
+        self.indent = indent;
+    }
+
+    /// Whether to write a comma after the last element of each array and
+    /// object which pretty-printing spreads over several lines, so adding
+    /// an element to the end changes only one line. Off by default.
+    ///
+    /// The output is not standard Json; only use this for consumers which
+    /// accept trailing commas, such as JSON5 parsers.
+    pub fn trailing_comma(&mut self, trailing: bool) {
+        self.trailing_comma = trailing;
+    }
+
+    /// Whether to write numbers in the canonical form produced by
+    /// `Json::coerce_numbers`, e.g. `1.0` as `1` and `1E2` as `100`, rather
+    /// than exactly as they were parsed. Unlike `coerce_numbers` this never
+    /// changes a value: numbers which an `f64` cannot hold exactly, such as
+    /// integers beyond 2^53 or ones too large for an `f64` at all, are
+    /// written unchanged. Off by default.
+    pub fn normalize_numbers(&mut self, normalize: bool) {
+        self.normalize_numbers = normalize;
+    }
+
+    /// How to write objects with duplicate keys. The default,
+    /// `DuplicateKeys::Keep`, writes them all, which some consumers reject.
+    /// Members which are written keep their original order.
+    pub fn on_duplicate_keys(&mut self, mode: DuplicateKeys) {
+        self.on_duplicate_keys = mode;
+    }
+
+    /// Serialize a Json object into a writer
+    pub fn write<W: io::Write>(&self, json: &Json, w: &mut W) -> io::Result<()> {
+        match self.max_width {
+            Some(width) => self.write_pretty(json, w, 0, 0, width),
+            None => self.write_compact(json, w),
+        }
+    }
+
+    /// Serialize a Json object into a `fmt::Write`, such as a `String` or a
+    /// `fmt::Formatter`, without intermediate buffering. Any error, such as
+    /// a duplicate key with `DuplicateKeys::Error`, becomes a `fmt::Error`.
+    pub fn write_to_fmt<W: fmt::Write>(&self, json: &Json, w: &mut W) -> fmt::Result {
+        self.write(json, &mut FmtAdapter(w)).map_err(|_| fmt::Error)
+    }
+
+    /// Whether `json` fits on one line in the space left before `width`
+    fn fits(&self, json: &Json, col: usize, width: usize) -> bool {
+        let mut measure = Measure { len: 0, limit: width.saturating_sub(col) };
+        col <= width && self.write_compact(json, &mut measure).is_ok()
+    }
+
+    /// Write a value starting at column `col` of a line indented `level` times
+    fn write_pretty<W: io::Write>(&self, json: &Json, w: &mut W, level: usize, col: usize, width: usize) -> io::Result<()> {
+        if json.is_empty() || self.fits(json, col, width) {
+            return self.write_compact(json, w);
+        }
+        let indent = (level + 1) * self.indent;
+        match json.0 {
+            JsonInner::Array(ref v) => {
+                w.write_all(b"[")?;
+                for (n, elem) in v.iter().enumerate() {
+                    w.write_all(if n == 0 { b"\n" } else { b",\n" })?;
+                    write!(w, "{:1$}", "", indent)?;
+                    self.write_pretty(elem, w, level + 1, indent, width)?;
                 }
+                if self.trailing_comma {
+                    w.write_all(b",")?;
+                }
+                write!(w, "\n{:1$}]", "", level * self.indent)?;
             }
-#[cfg(not(feature="utf16"))]
-            _ => { try!(write!(w, "{}", ch)); }
+            JsonInner::Object(ref v) => {
+                w.write_all(b"{")?;
+                let kept = self.kept_members(v);
+                let mut first = true;
+                for (n, (key, val)) in v.iter().enumerate() {
+                    if !self.keep_member(&kept, key, n)? {
+                        continue;
+                    }
+                    w.write_all(if first { b"\n" } else { b",\n" })?;
+                    first = false;
+                    write!(w, "{:1$}", "", indent)?;
+                    let mut key_bytes = vec![];
+                    self.write_string(key, &mut key_bytes)?;
+                    key_bytes.extend_from_slice(b": ");
+                    w.write_all(&key_bytes)?;
+                    self.write_pretty(val, w, level + 1, indent + key_bytes.len(), width)?;
+                }
+                if self.trailing_comma {
+                    w.write_all(b",")?;
+                }
+                write!(w, "\n{:1$}}}", "", level * self.indent)?;
+            }
+            // Scalars always fit, however long, since they cannot be split
+            _ => self.write_compact(json, w)?,
         }
+        Ok(())
     }
-    try!(w.write(b"\""));
-    Ok(())
-}
 
-/// The main serialization function
-pub fn serialize<W: io::Write>(json: &Json, w: &mut W) -> io::Result<()> {
-    match json.0 {
-        JsonInner::Null => { try!(w.write(b"null")); }
-        JsonInner::Bool(true) => { try!(w.write(b"true")); }
-        JsonInner::Bool(false) => { try!(w.write(b"false")); }
-        JsonInner::Number(ref s) => { try!(w.write(s.as_bytes())); }
-        JsonInner::String(ref s) => { try!(serialize_string(&s[..], &mut *w)); }
-        JsonInner::Array(ref v) => {
-            try!(w.write(b"["));
-            let mut first = true;
-            for elem in v {
-                if !first {
-                    try!(w.write(b", "));
+    /// Serialize a Json object into a writer on a single line, giving
+    /// `transform` the chance to substitute each node as it is reached,
+    /// before its children. If it returns a replacement, that is written
+    /// instead, as is, without calling `transform` on its children; if it
+    /// returns `None`, the node is written as usual. `max_width` is not
+    /// applied, since measuring nodes would call `transform` more than
+    /// once.
+    pub fn write_with<W, F>(&self, json: &Json, w: &mut W, mut transform: F) -> io::Result<()>
+        where W: io::Write,
+              F: FnMut(&Json) -> Option<Json>
+    {
+        self.write_compact_with(json, w, &mut transform)
+    }
+
+    /// Write a value on a single line
+    fn write_compact<W: io::Write>(&self, json: &Json, w: &mut W) -> io::Result<()> {
+        self.write_compact_with(json, w, &mut |_| None)
+    }
+
+    fn write_compact_with<W, F>(&self, json: &Json, w: &mut W, transform: &mut F) -> io::Result<()>
+        where W: io::Write,
+              F: FnMut(&Json) -> Option<Json>
+    {
+        if let Some(replacement) = transform(json) {
+            return self.write_compact(&replacement, w);
+        }
+        match json.0 {
+            JsonInner::Null => { w.write_all(b"null")?; }
+            JsonInner::Bool(true) => { w.write_all(b"true")?; }
+            JsonInner::Bool(false) => { w.write_all(b"false")?; }
+            JsonInner::Number(ref s) => {
+                let canon = if self.normalize_numbers { canonical_number_exact(s) } else { None };
+                w.write_all(canon.as_ref().unwrap_or(s).as_bytes())?;
+            }
+            JsonInner::String(ref s) => { self.write_string(&s[..], &mut *w)?; }
+            JsonInner::Array(ref v) => {
+                w.write_all(b"[")?;
+                let mut first = true;
+                for elem in v {
+                    if !first {
+                        w.write_all(b", ")?;
+                    }
+                    self.write_compact_with(elem, &mut *w, transform)?;
+                    first = false;
                 }
-                try!(serialize(elem, &mut *w));
-                first = false;
+                w.write_all(b"]")?;
             }
-            try!(w.write(b"]"));
-        }
-        JsonInner::Object(ref v) => {
-            try!(w.write(b"{"));
-            let mut first = true;
-            for &(ref key, ref val) in v {
-                if !first {
-                    try!(w.write(b", "));
+            JsonInner::Object(ref v) => {
+                w.write_all(b"{")?;
+                let kept = self.kept_members(v);
+                let mut first = true;
+                for (n, (key, val)) in v.iter().enumerate() {
+                    if !self.keep_member(&kept, key, n)? {
+                        continue;
+                    }
+                    if !first {
+                        w.write_all(b", ")?;
+                    }
+                    self.write_string(key, &mut *w)?;
+                    w.write_all(b": ")?;
+                    self.write_compact_with(val, &mut *w, transform)?;
+                    first = false;
                 }
-                try!(serialize_string(key, &mut *w));
-                try!(w.write(b": "));
-                try!(serialize(val, &mut *w));
-                first = false;
+                w.write_all(b"}")?;
             }
-            try!(w.write(b"}"));
+        };
+        Ok(())
+    }
+
+    /// Which members of an object to write, given how to treat duplicate
+    /// keys, or `None` if all of them are written. Worked out once for the
+    /// whole object, so that each member is not compared with every other.
+    fn kept_members(&self, members: &[(String, Json)]) -> Option<Vec<bool>> {
+        if self.on_duplicate_keys == DuplicateKeys::Keep {
+            return None;
         }
-    };
-    Ok(())
+        // The index of the member to write for each key
+        let mut chosen = HashMap::with_capacity(members.len());
+        for (n, (key, _)) in members.iter().enumerate() {
+            match chosen.entry(&key[..]) {
+                Entry::Occupied(mut entry) => {
+                    if self.on_duplicate_keys == DuplicateKeys::Last {
+                        entry.insert(n);
+                    }
+                }
+                Entry::Vacant(entry) => { entry.insert(n); }
+            }
+        }
+        let mut kept = vec![false; members.len()];
+        for &n in chosen.values() {
+            kept[n] = true;
+        }
+        Some(kept)
+    }
+
+    /// Whether to write the `n`th member of an object, given the members
+    /// `kept_members` chose, failing at the first duplicate if duplicate keys
+    /// are errors
+    fn keep_member(&self, kept: &Option<Vec<bool>>, key: &str, n: usize) -> io::Result<bool> {
+        match *kept {
+            Some(ref kept) if !kept[n] => {
+                if self.on_duplicate_keys == DuplicateKeys::Error {
+                    Err(io::Error::new(io::ErrorKind::InvalidData, format!("duplicate key `{}`", key)))
+                } else {
+                    Ok(false)
+                }
+            }
+            _ => Ok(true),
+        }
+    }
+
+    /// Serialize a string as a quoted and escaped Json string literal
+    pub fn write_string<W: io::Write>(&self, s: &str, w: W) -> io::Result<()> {
+        let mut adapter = IoAdapter { inner: w, error: None };
+        match self.write_string_fmt(s, &mut adapter) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(adapter.error.unwrap_or_else(|| io::Error::other("formatter error"))),
+        }
+    }
+
+    /// The number of bytes `write_string` would write for `s`, including
+    /// the quotes and escapes
+    pub fn string_len(&self, s: &str) -> usize {
+        let mut measure = Measure { len: 0, limit: usize::MAX };
+        self.write_string(s, &mut measure).expect("measuring cannot fail");
+        measure.len
+    }
+
+    /// Serialize a string as a quoted and escaped Json string literal into
+    /// a `fmt::Write`, such as a `String`, without intermediate buffering
+    pub fn write_string_fmt<W: fmt::Write>(&self, s: &str, w: &mut W) -> fmt::Result {
+        w.write_char('"')?;
+        for ch in s.chars() {
+            match ch {
+                '\x08' => { w.write_str("\\b")?; }
+                '\x0c' => { w.write_str("\\f")?; }
+                '\n' => { w.write_str("\\n")?; }
+                '\r' => { w.write_str("\\r")?; }
+                '\t' => { w.write_str("\\t")?; }
+                '\\' => { w.write_str("\\\\")?; }
+                '"' => { w.write_str("\\\"")?; }
+                '/' if self.escape_slash => { w.write_str("\\/")?; }
+                '<' | '>' | '&' | '\u{2028}' | '\u{2029}' if self.escape_html => {
+                    write!(w, "\\u{:04x}", ch as u32)?;
+                }
+                '\x20'..='\x7e' => { w.write_char(ch)?; }
+                // Characters outside the BMP become a surrogate pair
+                _ if cfg!(feature="utf16") || self.escape_non_ascii || ch.is_ascii() => {
+                    let mut utf16 = [0u16; 2];
+                    let subslice = ch.encode_utf16(&mut utf16);
+                    for word in subslice.iter().cloned() {
+                        write!(w, "\\u{:02x}{:02x}", word >> 8, word as u8)?;
+                    }
+                }
+                _ => { w.write_char(ch)?; }
+            }
+        }
+        w.write_char('"')
+    }
+}
+
+/// Adapts an `io::Write` to a `fmt::Write`, keeping the last IO error,
+/// since `fmt::Error` carries no information
+struct IoAdapter<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoAdapter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Adapts a `fmt::Write` to an `io::Write`. The serializer only ever
+/// writes whole UTF-8 strings, so each write is valid text.
+struct FmtAdapter<'a, W: 'a>(&'a mut W);
+
+impl<'a, W: fmt::Write> io::Write for FmtAdapter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.0.write_str(s).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// The main serialization function
+pub fn serialize<W: io::Write>(json: &Json, w: &mut W) -> io::Result<()> {
+    Writer::new().write(json, w)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io;
     use Json;
 
     fn round_trip(s: &str) -> bool {
@@ -173,6 +512,330 @@ mod tests {
         assert!(round_trip("{ \"key\": \"val\", \"true\": [] }"));
     }
 
+    #[test]
+    fn test_escape_modes() {
+        use super::Writer;
+
+        let json = Json::from_str("\"</script> & a/b \\u2028\\u2029\"").unwrap();
+
+        let mut html = Writer::new();
+        html.escape_html(true);
+        let mut out = vec![];
+        html.write(&json, &mut out).unwrap();
+        assert_eq!(out, &b"\"\\u003c/script\\u003e \\u0026 a/b \\u2028\\u2029\""[..]);
+        assert_eq!(Json::from_iter(out.iter().map(|x| Ok(*x))).unwrap(), json);
+
+        let mut slash = Writer::new();
+        slash.escape_slash(true);
+        let mut out = vec![];
+        slash.write(&json, &mut out).unwrap();
+        assert_eq!(&out[..18], &b"\"<\\/script> & a\\/b"[..]);
+
+        // Minimal is the default
+        let mut out = vec![];
+        Writer::new().write(&json, &mut out).unwrap();
+        assert_eq!(out, json.to_bytes());
+        assert_eq!(&out[..16], &b"\"</script> & a/b"[..]);
+        #[cfg(not(feature="utf16"))]
+        assert_eq!(&out[17..], "\u{2028}\u{2029}\"".as_bytes());
+    }
+
+    #[test]
+    fn test_string_len() {
+        use super::Writer;
+
+        for s in &["", "plain", "tab\tquote\"back\\slash</>", "caf\u{e9} \u{1d11e}", "\u{2028}&"] {
+            let json = Json::from(*s);
+            let len = json.string_serialized_len().unwrap();
+            assert_eq!(len, json.to_bytes().len(), "{:?}", s);
+
+            let mut html = Writer::new();
+            html.escape_html(true);
+            html.escape_slash(true);
+            let mut out = vec![];
+            html.write(&json, &mut out).unwrap();
+            assert_eq!(html.string_len(s), out.len(), "{:?}", s);
+        }
+
+        assert_eq!(Json::from("plain").string_serialized_len(), Some(7));
+        assert_eq!(Json::from("a\nb").string_serialized_len(), Some(6));
+        // Multi-byte characters are escaped as UTF-16 with the utf16 feature
+        #[cfg(feature="utf16")]
+        assert_eq!(Json::from("caf\u{e9}").string_serialized_len(), Some(11));
+        #[cfg(not(feature="utf16"))]
+        assert_eq!(Json::from("caf\u{e9}").string_serialized_len(), Some(7));
+        assert_eq!(Json::from_str("1").unwrap().string_serialized_len(), None);
+    }
+
+    #[test]
+    fn test_write_with() {
+        use super::Writer;
+
+        let json = Json::from_str("{\"price\": 1.005, \"items\": [{\"cost\": 2.499}, 3], \"name\": \"x\"}").unwrap();
+        let orig = json.clone();
+        let round = |node: &Json| {
+            if node.number_is_float() {
+                Some(Json::from_str(&format!("{:.2}", node.as_f64_lossy())).unwrap())
+            } else {
+                None
+            }
+        };
+
+        let mut out = vec![];
+        json.to_writer_with(&mut out, round).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"price\": 1.00, \"items\": [{\"cost\": 2.50}, 3], \"name\": \"x\"}");
+        assert_eq!(json, orig);
+
+        // Replacements are written as they are, not transformed again
+        let mut calls = 0;
+        let mut out = vec![];
+        Writer::new().write_with(&json, &mut out, |node| {
+            calls += 1;
+            if node.get("cost").is_some() { Some(Json::from_str("[1.5]").unwrap()) } else { None }
+        }).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"price\": 1.005, \"items\": [[1.5], 3], \"name\": \"x\"}");
+        assert_eq!(calls, 6);
+
+        // Without a replacement the output is the usual one
+        let mut out = vec![];
+        json.to_writer_with(&mut out, |_| None).unwrap();
+        assert_eq!(out, json.to_bytes());
+    }
+
+    #[test]
+    fn test_write_to_fmt() {
+        use std::fmt::Write;
+        use super::{DuplicateKeys, Writer};
+
+        let json = Json::from_str("{\"a\": [1, \"x\\u00e9\\n\"], \"b\": {\"c\": null}}").unwrap();
+        let mut out = String::from("prefix ");
+        json.write_to_fmt(&mut out).unwrap();
+        assert_eq!(out, format!("prefix {}", json));
+        assert_eq!(out.as_bytes()[7..], json.to_bytes()[..]);
+        write!(out, "{}", json).unwrap();
+        assert!(out.ends_with(&json.to_string()));
+
+        let mut pretty = Writer::new();
+        pretty.max_width(Some(10));
+        let mut out = String::new();
+        pretty.write_to_fmt(&json, &mut out).unwrap();
+        let mut bytes = vec![];
+        pretty.write(&json, &mut bytes).unwrap();
+        assert_eq!(out.as_bytes(), &bytes[..]);
+
+        let mut strict = Writer::new();
+        strict.on_duplicate_keys(DuplicateKeys::Error);
+        let dup = Json::from_str("{\"a\": 1, \"a\": 2}").unwrap();
+        assert!(strict.write_to_fmt(&dup, &mut String::new()).is_err());
+    }
+
+    #[test]
+    fn test_max_width() {
+        use super::Writer;
+
+        fn pretty(s: &str, width: usize) -> String {
+            let json = Json::from_str(s).unwrap();
+            let mut writer = Writer::new();
+            writer.max_width(Some(width));
+            let mut out = vec![];
+            writer.write(&json, &mut out).unwrap();
+            assert_eq!(Json::from_iter(out.iter().map(|x| Ok(*x))).unwrap(), json);
+            String::from_utf8(out).unwrap()
+        }
+
+        // Short values stay inline, exactly as the compact output
+        assert_eq!(pretty("[1,2,3]", 9), "[1, 2, 3]");
+        assert_eq!(pretty("{\"a\":[]}", 80), "{\"a\": []}");
+        assert_eq!(pretty("[]", 0), "[]");
+
+        // Long ones are expanded
+        assert_eq!(pretty("[1,2,3]", 8), "[\n  1,\n  2,\n  3\n]");
+
+        // Each level is checked against the width separately
+        let s = "{\"a\": [1, 2, 3], \"b\": [10000000, 20000000, 30000000], \"c\": {\"d\": [\"xyz\"]}}";
+        assert_eq!(pretty(s, 21), "{\n  \"a\": [1, 2, 3],\n  \"b\": [\n    10000000,\n    20000000,\n    30000000\n  ],\n  \"c\": {\"d\": [\"xyz\"]}\n}");
+        assert_eq!(pretty(s, 17), "{\n  \"a\": [1, 2, 3],\n  \"b\": [\n    10000000,\n    20000000,\n    30000000\n  ],\n  \"c\": {\n    \"d\": [\"xyz\"]\n  }\n}");
+        assert_eq!(pretty("[[[1, 2]]]", 6), "[\n  [\n    [\n      1,\n      2\n    ]\n  ]\n]");
+
+        // Scalars are never split, even if too wide
+        assert_eq!(pretty("[\"a long string\"]", 4), "[\n  \"a long string\"\n]");
+    }
+
+    #[test]
+    fn test_trailing_comma() {
+        use super::Writer;
+
+        let json = Json::from_str("{\"a\": [1, 2], \"b\": [10000000, 20000000]}").unwrap();
+        let mut writer = Writer::new();
+        writer.max_width(Some(16));
+
+        let mut out = vec![];
+        writer.write(&json, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\n  \"a\": [1, 2],\n  \"b\": [\n    10000000,\n    20000000\n  ]\n}");
+
+        // Only expanded arrays and objects get a trailing comma
+        writer.trailing_comma(true);
+        let mut out = vec![];
+        writer.write(&json, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\n  \"a\": [1, 2],\n  \"b\": [\n    10000000,\n    20000000,\n  ],\n}");
+    }
+
+    #[test]
+    fn test_normalize_numbers() {
+        use super::Writer;
+        use JsonInner;
+
+        fn write(writer: &Writer, json: &Json) -> String {
+            let mut out = vec![];
+            writer.write(json, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        }
+
+        // As from a lenient parse
+        let plus = Json(JsonInner::Number("+5".to_owned()));
+        let json = Json::from_str("[1.0, 1E2, 0.50, 1e999, \"1.0\"]").unwrap();
+
+        let mut writer = Writer::new();
+        assert_eq!(write(&writer, &json), "[1.0, 1E2, 0.50, 1e999, \"1.0\"]");
+        assert_eq!(write(&writer, &plus), "+5");
+
+        writer.normalize_numbers(true);
+        assert_eq!(write(&writer, &json), "[1, 100, 0.5, 1e999, \"1.0\"]");
+        // Values are never changed, only how they are written
+        let exact = Json::from_str("[12345678901234567891, 9007199254740993.0, 0.1000000000000000000001, \
+                                     -0.0, 5e-7, 0.000001, 1.5E+21, 3.14159265358979323846]").unwrap();
+        assert_eq!(write(&writer, &exact), "[12345678901234567891, 9007199254740993.0, 0.1000000000000000000001, \
+                                            -0, 5e-7, 0.000001, 1.5e21, 3.14159265358979323846]");
+        assert_eq!(write(&writer, &plus), "5");
+        // The tree itself is untouched
+        assert_eq!(json.array().unwrap()[0].num(), Some("1.0"));
+
+        // Normalizing on output matches normalizing the tree
+        let mut coerced = json.clone();
+        coerced.coerce_numbers();
+        assert_eq!(write(&writer, &json), coerced.to_string());
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        use super::{DuplicateKeys, Writer};
+
+        let json = Json::from_str("{\"a\": 1, \"b\": [{\"c\": 2, \"c\": 3}], \"a\": 4, \"d\": 5, \"a\": 6}").unwrap();
+        let write = |mode, width| {
+            let mut writer = Writer::new();
+            writer.on_duplicate_keys(mode);
+            writer.max_width(width);
+            let mut out = vec![];
+            writer.write(&json, &mut out).map(|_| String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!(write(DuplicateKeys::Keep, None).unwrap(), json.to_string());
+        assert_eq!(write(DuplicateKeys::First, None).unwrap(), "{\"a\": 1, \"b\": [{\"c\": 2}], \"d\": 5}");
+        assert_eq!(write(DuplicateKeys::Last, None).unwrap(), "{\"b\": [{\"c\": 3}], \"d\": 5, \"a\": 6}");
+        assert_eq!(write(DuplicateKeys::Last, Some(14)).unwrap(), "{\n  \"b\": [\n    {\"c\": 3}\n  ],\n  \"d\": 5,\n  \"a\": 6\n}");
+
+        let err = write(DuplicateKeys::Error, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "duplicate key `c`");
+        assert!(write(DuplicateKeys::Error, Some(10)).is_err());
+
+        let unique = Json::from_str("{\"a\": 1, \"b\": {\"a\": 2}}").unwrap();
+        let mut writer = Writer::new();
+        writer.on_duplicate_keys(DuplicateKeys::Error);
+        let mut out = vec![];
+        writer.write(&unique, &mut out).unwrap();
+        assert_eq!(out, unique.to_bytes());
+
+        // Large objects are handled in linear time
+        let big: Vec<(String, Json)> = (0..100_000).map(|n| (format!("k{}", n % 100), Json::from(n))).collect();
+        let mut writer = Writer::new();
+        writer.on_duplicate_keys(DuplicateKeys::Last);
+        let mut out = vec![];
+        writer.write(&Json::from(big), &mut out).unwrap();
+        let written = Json::from_slice(&out).unwrap();
+        assert_eq!(written.len(), 100);
+        assert_eq!(written.get("k0"), Some(&Json::from(99_900)));
+    }
+
+    #[test]
+    fn test_write_error() {
+        // Accepts `room` bytes, then fails
+        struct Limited {
+            out: Vec<u8>,
+            room: usize,
+        }
+
+        impl io::Write for Limited {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.room == 0 {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, "sink full"));
+                }
+                let n = buf.len().min(self.room);
+                self.out.extend_from_slice(&buf[..n]);
+                self.room -= n;
+                Ok(n)
+            }
+            fn flush(&mut self) -> io::Result<()> { Ok(()) }
+        }
+
+        let json = Json::from_str("{\"key\": [null, true, 1.5, \"a\\nb\\u00e9\"], \"x\": {}}").unwrap();
+        let full = json.to_bytes();
+        for room in 0..full.len() {
+            let mut sink = Limited { out: vec![], room };
+            let err = json.write_to(&mut sink).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WriteZero, "{}", room);
+            assert_eq!(err.to_string(), "sink full");
+            // Everything before the failure was written straight through
+            assert_eq!(sink.out, &full[..room]);
+        }
+        let mut sink = Limited { out: vec![], room: full.len() };
+        json.write_to(&mut sink).unwrap();
+        assert_eq!(sink.out, full);
+    }
+
+    #[test]
+    fn test_escape_non_ascii() {
+        use super::Writer;
+
+        let mut writer = Writer::new();
+        writer.escape_non_ascii(true);
+        let write = |json: &Json| {
+            let mut out = String::new();
+            writer.write_to_fmt(json, &mut out).unwrap();
+            out
+        };
+
+        let json = Json::from("a\u{e9}\u{20ac}\u{1d11e}\u{10ffff}\x7f\n");
+        let out = write(&json);
+        assert_eq!(out, "\"a\\u00e9\\u20ac\\ud834\\udd1e\\udbff\\udfff\\u007f\\n\"");
+        assert_eq!(Json::from_str(&out).unwrap(), json);
+
+        // Keys too
+        let json = Json::from_str("{\"\\u00fcber\": [\"\\ud83d\\ude00\"]}").unwrap();
+        assert_eq!(json["\u{fc}ber"][0], Json::from("\u{1f600}"));
+        assert_eq!(write(&json), "{\"\\u00fcber\": [\"\\ud83d\\ude00\"]}");
+        assert_eq!(write(&json), json.to_string_ascii());
+    }
+
+    #[test]
+    fn test_control_characters() {
+        // `\b` is backspace, not bell
+        let json = Json::from_str("\"\\b\"").unwrap();
+        assert_eq!(json.string(), Some("\x08"));
+        assert_eq!(json.to_string(), "\"\\b\"");
+
+        // Every control character is escaped, with or without `utf16`
+        let all: String = (0u8..0x20).map(char::from).collect();
+        let json = Json::from(&all[..]);
+        let text = json.to_string();
+        assert!(text.bytes().all(|b| b >= 0x20), "{:?}", text);
+        assert_eq!(Json::from_str(&text).unwrap(), json);
+
+        let doc = Json::from_str("{\"a\\u0007\": [\"\\f\\t\", -1.50e+3, null]}").unwrap();
+        assert_eq!(Json::from_str(&doc.to_string()).unwrap(), doc);
+        assert_eq!(doc.to_string(), "{\"a\\u0007\": [\"\\f\\t\", -1.50e+3, null]}");
+    }
+
     #[test]
     #[cfg(feature="utf16")]
     fn test_round_trip_utf16() {